@@ -0,0 +1,66 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use risc0_zkvm::Receipt;
+use risc_v::{phi, phi_raw, verify, worker, Encoding, Mode, VerifyRequest};
+use std::env;
+
+// Fibonacci positions to benchmark proving with.
+const INPUTS: [u64; 3] = [1, 10, 93];
+
+/// Disables memoization so every iteration proves from scratch, and verifies
+/// against the image id of the embedded guest ELF.
+fn setup() -> String {
+    env::set_var("FIB_CACHE_SIZE", "0");
+    let image_id = worker::image_id_info().unwrap().computed;
+    env::set_var("IMAGE_ID", &image_id);
+    image_id
+}
+
+fn bench_prove(c: &mut Criterion) {
+    setup();
+    let mut group = c.benchmark_group("prove");
+    group.sample_size(10);
+    for n in INPUTS {
+        let size = phi_raw(n, Mode::Nth).unwrap().proof.len();
+        println!("prove/{}: receipt size {} bytes", n, size);
+        group.bench_with_input(BenchmarkId::from_parameter(n), &n, |b, &n| {
+            b.iter(|| phi_raw(n, Mode::Nth).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn bench_verify(c: &mut Criterion) {
+    let image_id = setup();
+    let result = phi(10, Mode::Nth, Encoding::Hex).unwrap();
+    let request = VerifyRequest {
+        proof: result.proof,
+        image_id,
+        encoding: Encoding::Hex,
+        mode: Some(Mode::Nth),
+        nonce: None,
+    };
+    c.bench_function("verify", |b| b.iter(|| assert!(verify(&request).is_valid)));
+}
+
+fn bench_serialization(c: &mut Criterion) {
+    setup();
+    let receipt_bytes = phi_raw(10, Mode::Nth).unwrap().proof;
+    c.bench_function("receipt/bincode", |b| {
+        b.iter(|| {
+            let receipt: Receipt = bincode::deserialize(&receipt_bytes).unwrap();
+            bincode::serialize(&receipt).unwrap()
+        })
+    });
+    for encoding in [Encoding::Hex, Encoding::Base64] {
+        let name = match encoding {
+            Encoding::Hex => "receipt/hex",
+            Encoding::Base64 => "receipt/base64",
+        };
+        c.bench_function(name, |b| {
+            b.iter(|| encoding.decode(&encoding.encode(&receipt_bytes)).unwrap())
+        });
+    }
+}
+
+criterion_group!(benches, bench_prove, bench_verify, bench_serialization);
+criterion_main!(benches);
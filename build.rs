@@ -0,0 +1,38 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+// Export the git commit the binary was built from as GIT_SHA, for /version.
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    let sha = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok());
+    if let Some(sha) = sha {
+        println!("cargo:rustc-env=GIT_SHA={}", sha.trim());
+    }
+
+    // Rebuild when HEAD moves to another branch or the current branch gains
+    // a commit. Outside a git checkout there is nothing to watch.
+    let git = Path::new(".git");
+    if !git.is_dir() {
+        return;
+    }
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    let branch = fs::read_to_string(git.join("HEAD"))
+        .ok()
+        .and_then(|head| Some(head.strip_prefix("ref: ")?.trim().to_string()));
+    if let Some(branch) = branch {
+        let branch = git.join(branch);
+        if branch.is_file() {
+            println!("cargo:rerun-if-changed={}", branch.display());
+        }
+    }
+    if git.join("packed-refs").is_file() {
+        println!("cargo:rerun-if-changed=.git/packed-refs");
+    }
+}
@@ -1,21 +1,60 @@
 #![no_main]
 #![no_std]
 
+extern crate alloc;
+
+use alloc::vec::Vec;
 use risc0_zkvm::guest::env;
 
 risc0_zkvm::guest::entry!(main);
 
+// Computation modes selectable by the host.
+const MODE_NTH: u32 = 0; // fib(n) as a u64
+const MODE_WIDE: u32 = 1; // fib(n) as a u128
+const MODE_SEQUENCE: u32 = 2; // fib(1..=n) as a list of u64
+
+// Operations selectable by the host.
+const OP_FIB: u32 = 0; // Fibonacci, in the selected mode
+const OP_FACTORIAL: u32 = 1; // n! as a u128
+
 fn main() {
-    // Read the Fibonacci position from the host.
+    // Read the position, the computation mode, the operation and an optional
+    // challenge nonce from the host.
     let n: u64 = env::read();
+    let mode: u32 = env::read();
+    let op: u32 = env::read();
+    let nonce: Option<[u8; 16]> = env::read();
     
     // Ensure the input is at least 1.
     if n < 1 {
         panic!("Input must be at least 1");
     }
     
-    // Compute the Fibonacci number.
-    let fib = if n == 1 || n == 2 {
+    // Compute the result and commit it after the operation, the mode, the
+    // input and the nonce, so the journal says what it proves: a verifier
+    // decodes the result according to the committed tag rather than one
+    // supplied by a client, and can check the nonce for freshness.
+    match (op, mode) {
+        (OP_FIB, MODE_NTH) => env::commit(&(op, mode, n, nonce, fib_u64(n))),
+        (OP_FIB, MODE_WIDE) => env::commit(&(op, mode, n, nonce, fib_u128(n))),
+        (OP_FIB, MODE_SEQUENCE) => env::commit(&(op, mode, n, nonce, fib_sequence(n))),
+        (OP_FACTORIAL, MODE_NTH) => env::commit(&(op, mode, n, nonce, factorial(n))),
+        _ => panic!("Unknown operation or mode"),
+    }
+}
+
+// Computes n! in u128, which overflows for n > 34.
+fn factorial(n: u64) -> u128 {
+    let mut result: u128 = 1;
+    for i in 2..=n as u128 {
+        result = result.checked_mul(i).expect("Integer overflow");
+    }
+    result
+}
+
+// Computes fib(n) in u64, which overflows for n > 93.
+fn fib_u64(n: u64) -> u64 {
+    if n == 1 || n == 2 {
         1
     } else {
         let mut a: u64 = 1; // Fibonacci(1)
@@ -26,8 +65,37 @@ fn main() {
             b = c;
         }
         b
-    };
+    }
+}
 
-    // Commit the Fibonacci number as the guest's output.
-    env::commit(&fib);
+// Computes fib(n) in u128, which overflows for n > 186.
+fn fib_u128(n: u64) -> u128 {
+    if n == 1 || n == 2 {
+        1
+    } else {
+        let mut a: u128 = 1; // Fibonacci(1)
+        let mut b: u128 = 1; // Fibonacci(2)
+        for _ in 3..=n {
+            let c = a.checked_add(b).expect("Integer overflow");
+            a = b;
+            b = c;
+        }
+        b
+    }
+}
+
+// Computes fib(1..=n) in u64, which overflows for n > 93.
+fn fib_sequence(n: u64) -> Vec<u64> {
+    let mut sequence: Vec<u64> = Vec::with_capacity(n as usize);
+    for i in 0..n as usize {
+        let fib = if i < 2 {
+            1
+        } else {
+            sequence[i - 2]
+                .checked_add(sequence[i - 1])
+                .expect("Integer overflow")
+        };
+        sequence.push(fib);
+    }
+    sequence
 }
@@ -0,0 +1,140 @@
+use crate::config::env_secs_or;
+use crate::error::Error;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+// Default lifetime of a challenge (5 minutes) if not provided via an
+// environment variable.
+const DEFAULT_CHALLENGE_TTL_SECS: &str = "300";
+
+// Maximum number of outstanding challenges. Issuing more fails until some are
+// redeemed or expire. GET /challenge is unauthenticated, so a client can fill
+// every slot and block issuance for up to CHALLENGE_TTL_SECS; rate-limit it in
+// front of the server.
+const MAX_CHALLENGES: usize = 65536;
+
+/// A challenge nonce bound into a receipt's journal.
+pub type Nonce = [u8; 16];
+
+// Define the response returned when a challenge is issued.
+#[derive(Serialize)]
+pub struct Challenge {
+    pub nonce: String,
+    pub expires_in_secs: u64,
+}
+
+/// Outstanding challenge nonces with the time they were issued. A receipt
+/// committing a nonce is only accepted while its challenge is outstanding,
+/// and accepting it redeems the challenge, so the receipt cannot be replayed.
+pub struct Challenges {
+    ttl: Duration,
+    issued: Mutex<HashMap<Nonce, Instant>>,
+}
+
+static CHALLENGES: OnceLock<Challenges> = OnceLock::new();
+
+/// Returns the process-wide challenges, whose lifetime is read from the
+/// CHALLENGE_TTL_SECS environment variable on first use. Panics if the value
+/// is invalid, so call it at startup.
+pub fn challenges() -> &'static Challenges {
+    CHALLENGES.get_or_init(|| Challenges {
+        ttl: env_secs_or("CHALLENGE_TTL_SECS", DEFAULT_CHALLENGE_TTL_SECS),
+        issued: Mutex::new(HashMap::new()),
+    })
+}
+
+/// Parses a hex-encoded challenge nonce.
+pub fn parse_nonce(nonce: &str) -> Result<Nonce, Error> {
+    let bytes = hex::decode(nonce).map_err(|e| Error::Challenge(e.to_string()))?;
+    bytes.try_into().map_err(|bytes: Vec<u8>| {
+        Error::Challenge(format!(
+            "expected a 16-byte nonce, got {} bytes",
+            bytes.len()
+        ))
+    })
+}
+
+impl Challenges {
+    /// Issues a fresh random nonce.
+    pub fn issue(&self) -> Result<Challenge, Error> {
+        let mut issued = self.issued.lock().unwrap();
+        if issued.len() >= MAX_CHALLENGES {
+            issued.retain(|_, issued_at| issued_at.elapsed() < self.ttl);
+            if issued.len() >= MAX_CHALLENGES {
+                return Err(Error::TooManyChallenges(MAX_CHALLENGES));
+            }
+        }
+        // A v4 UUID carries 122 random bits from the OS generator.
+        let nonce = *Uuid::new_v4().as_bytes();
+        issued.insert(nonce, Instant::now());
+        Ok(Challenge {
+            nonce: hex::encode(nonce),
+            expires_in_secs: self.ttl.as_secs(),
+        })
+    }
+
+    /// Checks that a nonce was issued and has neither expired nor been
+    /// redeemed.
+    pub fn check(&self, nonce: &Nonce) -> Result<(), Error> {
+        match self.issued.lock().unwrap().get(nonce) {
+            Some(issued_at) if issued_at.elapsed() < self.ttl => Ok(()),
+            _ => Err(Error::Challenge(
+                "unknown, expired or already used nonce".to_string(),
+            )),
+        }
+    }
+
+    /// Checks a nonce like [`Challenges::check`] and redeems it, so it is not
+    /// accepted again.
+    pub fn redeem(&self, nonce: &Nonce) -> Result<(), Error> {
+        match self.issued.lock().unwrap().remove(nonce) {
+            Some(issued_at) if issued_at.elapsed() < self.ttl => Ok(()),
+            _ => Err(Error::Challenge(
+                "unknown, expired or already used nonce".to_string(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn challenges(ttl: Duration) -> Challenges {
+        Challenges {
+            ttl,
+            issued: Mutex::new(HashMap::new()),
+        }
+    }
+
+    #[test]
+    fn redeems_a_nonce_once() {
+        let challenges = challenges(Duration::from_secs(60));
+        let nonce = parse_nonce(&challenges.issue().unwrap().nonce).unwrap();
+        assert!(challenges.check(&nonce).is_ok());
+        assert!(challenges.redeem(&nonce).is_ok());
+        assert!(challenges.check(&nonce).is_err());
+        assert!(challenges.redeem(&nonce).is_err());
+    }
+
+    #[test]
+    fn rejects_expired_and_unknown_nonces() {
+        let challenges = challenges(Duration::ZERO);
+        let nonce = parse_nonce(&challenges.issue().unwrap().nonce).unwrap();
+        assert!(matches!(
+            challenges.redeem(&nonce),
+            Err(Error::Challenge(_))
+        ));
+        assert!(challenges.check(&[0; 16]).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_nonces() {
+        assert!(parse_nonce("zz").is_err());
+        assert!(parse_nonce("00").is_err());
+        assert!(parse_nonce(&"00".repeat(16)).is_ok());
+    }
+}
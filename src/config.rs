@@ -0,0 +1,45 @@
+use std::env;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Reads and parses the environment variable `name`, falling back to
+/// `default` when it is unset. Panics if the value does not parse, so call it
+/// at startup.
+pub fn env_or<T>(name: &str, default: &str) -> T
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let value = env::var(name).unwrap_or_else(|_| default.to_string());
+    value
+        .parse()
+        .unwrap_or_else(|e| panic!("Invalid {} '{}': {}", name, value, e))
+}
+
+/// Reads a duration in whole seconds like [`env_or`].
+pub fn env_secs_or(name: &str, default: &str) -> Duration {
+    Duration::from_secs(env_or(name, default))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn falls_back_to_the_default() {
+        env::remove_var("CONFIG_TEST_UNSET");
+        assert_eq!(env_or::<u64>("CONFIG_TEST_UNSET", "42"), 42);
+        assert_eq!(
+            env_secs_or("CONFIG_TEST_UNSET", "5"),
+            Duration::from_secs(5)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid CONFIG_TEST_INVALID 'ten'")]
+    fn rejects_unparsable_values() {
+        env::set_var("CONFIG_TEST_INVALID", "ten");
+        env_or::<u64>("CONFIG_TEST_INVALID", "10");
+    }
+}
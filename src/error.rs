@@ -0,0 +1,192 @@
+use serde::Serialize;
+use thiserror::Error;
+
+/// Errors produced while proving or verifying a Fibonacci receipt.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Input out of range: expected {min} <= n <= {max}, got {got}")]
+    InputOutOfRange { min: u64, max: u64, got: u64 },
+
+    #[error("Invalid hex string for IMAGE_ID: {0}")]
+    ImageIdHex(hex::FromHexError),
+
+    #[error("Invalid IMAGE_ID length: expected 32 bytes, got {0}")]
+    ImageIdLength(usize),
+
+    #[error("Failed to compute image id of the guest ELF: {0}")]
+    ImageIdCompute(String),
+
+    #[error("Invalid hex string for proof: {0}")]
+    ProofHex(hex::FromHexError),
+
+    #[error("Invalid base64 string for proof: {0}")]
+    ProofBase64(base64::DecodeError),
+
+    #[error("Failed to write input a: {0}")]
+    WriteInput(String),
+
+    #[error("Failed to build environment: {0}")]
+    BuildEnv(String),
+
+    #[error("Prover backend unavailable: {0}")]
+    ProverUnavailable(String),
+
+    #[error("Failed to execute guest: {0}")]
+    Prove(String),
+
+    #[error("Proving timed out after {0} seconds")]
+    Timeout(u64),
+
+    #[error("Proving task failed: {0}")]
+    TaskFailed(String),
+
+    #[error("Guest produced no output: the journal is empty")]
+    EmptyJournal,
+
+    #[error("Failed to decode output: {0}")]
+    Decode(String),
+
+    #[error("Journal input mismatch: expected {expected}, got {got}")]
+    JournalMismatch { expected: u64, got: u64 },
+
+    #[error("Receipt verification failed: {0}")]
+    Verification(String),
+
+    #[error("Failed to serialize receipt: {0}")]
+    Serialize(bincode::Error),
+
+    #[error("Failed to deserialize receipt: {0}")]
+    Deserialize(bincode::Error),
+
+    #[error("Database error: {0}")]
+    Database(rusqlite::Error),
+
+    #[error("Idempotency-Key '{0}' was already used with a different input or mode")]
+    IdempotencyKeyReused(String),
+
+    #[error("Job queue is full: {0} jobs are pending or awaiting collection")]
+    QueueFull(usize),
+
+    #[error("Invalid challenge: {0}")]
+    Challenge(String),
+
+    #[error("Too many outstanding challenges: at most {0} may be pending")]
+    TooManyChallenges(usize),
+}
+
+impl Error {
+    /// Stable, machine-readable classification of the error for clients.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::InputOutOfRange { .. } => "input_out_of_range",
+            Error::ImageIdHex(_) | Error::ImageIdLength(_) | Error::ImageIdCompute(_) => {
+                "invalid_image_id"
+            }
+            Error::ProofHex(_) | Error::ProofBase64(_) | Error::Deserialize(_) => "invalid_proof",
+            Error::ProverUnavailable(_) => "prover_unavailable",
+            Error::Timeout(_) => "timeout",
+            Error::WriteInput(_)
+            | Error::BuildEnv(_)
+            | Error::Prove(_)
+            | Error::EmptyJournal
+            | Error::Decode(_)
+            | Error::JournalMismatch { .. }
+            | Error::Serialize(_)
+            | Error::TaskFailed(_) => "proving_failed",
+            Error::Verification(_) => "verification_failed",
+            Error::Database(_) => "database",
+            Error::IdempotencyKeyReused(_) => "idempotency_key_reused",
+            Error::QueueFull(_) => "queue_full",
+            Error::Challenge(_) => "invalid_challenge",
+            Error::TooManyChallenges(_) => "too_many_challenges",
+        }
+    }
+}
+
+// Define the JSON body returned for a failed request.
+#[derive(Clone, Serialize)]
+pub struct ErrorResponse {
+    pub error: String,
+    pub kind: &'static str,
+}
+
+impl ErrorResponse {
+    /// Builds the body for a failure that is not an [`Error`], such as a
+    /// request rejected before it reaches a handler.
+    pub fn new(kind: &'static str, error: impl Into<String>) -> Self {
+        ErrorResponse {
+            error: error.into(),
+            kind,
+        }
+    }
+}
+
+impl From<&Error> for ErrorResponse {
+    fn from(e: &Error) -> Self {
+        ErrorResponse {
+            error: e.to_string(),
+            kind: e.kind(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_errors_by_kind() {
+        let cases = [
+            (
+                Error::InputOutOfRange {
+                    min: 1,
+                    max: 93,
+                    got: 94,
+                },
+                "input_out_of_range",
+            ),
+            (Error::ImageIdLength(16), "invalid_image_id"),
+            (
+                Error::ProverUnavailable("r0vm".to_string()),
+                "prover_unavailable",
+            ),
+            (Error::Timeout(60), "timeout"),
+            (
+                Error::TaskFailed("task panicked".to_string()),
+                "proving_failed",
+            ),
+            (Error::EmptyJournal, "proving_failed"),
+            (
+                Error::Verification("bad seal".to_string()),
+                "verification_failed",
+            ),
+            (
+                Error::IdempotencyKeyReused("key".to_string()),
+                "idempotency_key_reused",
+            ),
+            (Error::QueueFull(1024), "queue_full"),
+            (Error::Challenge("expired".to_string()), "invalid_challenge"),
+            (Error::TooManyChallenges(65536), "too_many_challenges"),
+        ];
+        for (error, kind) in cases {
+            assert_eq!(error.kind(), kind, "{}", error);
+        }
+    }
+
+    #[test]
+    fn serializes_the_error_response() {
+        let error = Error::InputOutOfRange {
+            min: 1,
+            max: 93,
+            got: 94,
+        };
+        let body = serde_json::to_value(ErrorResponse::from(&error)).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "error": "Input out of range: expected 1 <= n <= 93, got 94",
+                "kind": "input_out_of_range",
+            })
+        );
+    }
+}
@@ -0,0 +1,292 @@
+use crate::error::Error;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Define a generated proof as recorded in the history.
+#[derive(Serialize)]
+pub struct ProofRecord {
+    pub id: i64,
+    pub circuit: String,
+    pub mode: String,
+    pub public_input: String,
+    pub proof: String,
+    pub created_at: i64,
+}
+
+// Define a recorded proof as listed, without the hex-encoded receipt, which
+// is fetched by id.
+#[derive(Serialize)]
+pub struct ProofSummary {
+    pub id: i64,
+    pub circuit: String,
+    pub mode: String,
+    pub public_input: String,
+    pub created_at: i64,
+}
+
+// Define the filters and the page accepted when listing recorded proofs.
+#[derive(Default, Deserialize)]
+pub struct ProofQuery {
+    pub circuit: Option<String>,
+    pub since: Option<i64>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+// Number of proofs listed when the query does not set a limit.
+const DEFAULT_LIST_LIMIT: u32 = 100;
+
+/// Largest number of proofs listed at once; larger limits are capped.
+pub const MAX_LIST_LIMIT: u32 = 1000;
+
+/// SQLite-backed audit trail of generated proofs and verification results.
+pub struct History {
+    conn: Mutex<Connection>,
+}
+
+// Process-wide history, opened from the DB_PATH environment variable on
+// first use. Persistence is disabled when DB_PATH is unset.
+static HISTORY: OnceLock<Option<History>> = OnceLock::new();
+
+/// Returns the process-wide history, or `None` if DB_PATH is unset. Panics if
+/// DB_PATH is set but the database cannot be opened, so call it at startup.
+pub fn history() -> Option<&'static History> {
+    HISTORY
+        .get_or_init(|| {
+            let path = env::var("DB_PATH").ok()?;
+            let history = History::open(&path)
+                .unwrap_or_else(|e| panic!("Failed to open DB_PATH '{}': {}", path, e));
+            Some(history)
+        })
+        .as_ref()
+}
+
+/// Current unix time in milliseconds.
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
+impl History {
+    /// Opens (or creates) the database at `path` and ensures the schema exists.
+    pub fn open(path: &str) -> Result<Self, Error> {
+        let conn = Connection::open(path).map_err(Error::Database)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS proofs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                circuit TEXT NOT NULL,
+                mode TEXT NOT NULL,
+                public_input TEXT NOT NULL,
+                proof TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS verifications (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                image_id TEXT NOT NULL,
+                op TEXT,
+                mode TEXT NOT NULL,
+                public_input TEXT,
+                is_valid INTEGER NOT NULL,
+                created_at INTEGER NOT NULL
+            );",
+        )
+        .map_err(Error::Database)?;
+        Ok(History {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Records a generated proof, hex-encoded, and returns its id.
+    pub fn record_proof(
+        &self,
+        circuit: &str,
+        mode: &str,
+        public_input: &str,
+        proof: &str,
+    ) -> Result<i64, Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO proofs (circuit, mode, public_input, proof, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![circuit, mode, public_input, proof, now_ms()],
+        )
+        .map_err(Error::Database)?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Records the outcome of a verification and returns its id. The
+    /// operation is `None` when the receipt could not be decoded.
+    pub fn record_verification(
+        &self,
+        image_id: &str,
+        op: Option<&str>,
+        mode: &str,
+        public_input: Option<&str>,
+        is_valid: bool,
+    ) -> Result<i64, Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO verifications (image_id, op, mode, public_input, is_valid, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![image_id, op, mode, public_input, is_valid, now_ms()],
+        )
+        .map_err(Error::Database)?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Looks up a recorded proof by id.
+    pub fn get_proof(&self, id: i64) -> Result<Option<ProofRecord>, Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT id, circuit, mode, public_input, proof, created_at
+             FROM proofs WHERE id = ?1",
+            params![id],
+            proof_from_row,
+        )
+        .optional()
+        .map_err(Error::Database)
+    }
+
+    /// Lists recorded proofs, optionally filtered by circuit and by a minimum
+    /// creation time in unix milliseconds, oldest first. At most `limit`
+    /// proofs (default 100, capped at [`MAX_LIST_LIMIT`]) are listed, after
+    /// skipping `offset`.
+    pub fn list_proofs(&self, query: &ProofQuery) -> Result<Vec<ProofSummary>, Error> {
+        let limit = query
+            .limit
+            .unwrap_or(DEFAULT_LIST_LIMIT)
+            .min(MAX_LIST_LIMIT);
+        let offset = query.offset.unwrap_or(0);
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn
+            .prepare(
+                "SELECT id, circuit, mode, public_input, created_at
+                 FROM proofs
+                 WHERE (?1 IS NULL OR circuit = ?1) AND (?2 IS NULL OR created_at >= ?2)
+                 ORDER BY id
+                 LIMIT ?3 OFFSET ?4",
+            )
+            .map_err(Error::Database)?;
+        let rows = statement
+            .query_map(
+                params![query.circuit, query.since, limit, offset],
+                summary_from_row,
+            )
+            .map_err(Error::Database)?;
+        rows.collect::<Result<_, _>>().map_err(Error::Database)
+    }
+}
+
+fn proof_from_row(row: &Row) -> rusqlite::Result<ProofRecord> {
+    Ok(ProofRecord {
+        id: row.get(0)?,
+        circuit: row.get(1)?,
+        mode: row.get(2)?,
+        public_input: row.get(3)?,
+        proof: row.get(4)?,
+        created_at: row.get(5)?,
+    })
+}
+
+fn summary_from_row(row: &Row) -> rusqlite::Result<ProofSummary> {
+    Ok(ProofSummary {
+        id: row.get(0)?,
+        circuit: row.get(1)?,
+        mode: row.get(2)?,
+        public_input: row.get(3)?,
+        created_at: row.get(4)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history_with(proofs: &[(&str, &str)]) -> History {
+        let history = History::open(":memory:").unwrap();
+        for (circuit, public_input) in proofs {
+            history
+                .record_proof(circuit, "nth", public_input, "c0ffee")
+                .unwrap();
+        }
+        history
+    }
+
+    #[test]
+    fn looks_up_recorded_proofs_by_id() {
+        let history = history_with(&[]);
+        let id = history.record_proof("fib", "wide", "90", "c0ffee").unwrap();
+
+        let record = history.get_proof(id).unwrap().unwrap();
+        assert_eq!(record.id, id);
+        assert_eq!(record.circuit, "fib");
+        assert_eq!(record.mode, "wide");
+        assert_eq!(record.public_input, "90");
+        assert_eq!(record.proof, "c0ffee");
+        assert!(history.get_proof(id + 1).unwrap().is_none());
+    }
+
+    // Reads back the operation and mode of every recorded verification.
+    fn verifications(history: &History) -> Vec<(Option<String>, String)> {
+        let conn = history.conn.lock().unwrap();
+        let mut statement = conn
+            .prepare("SELECT op, mode FROM verifications ORDER BY id")
+            .unwrap();
+        let rows = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        rows.collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn records_the_operation_of_each_verification() {
+        let history = history_with(&[]);
+        history
+            .record_verification("00", Some("factorial"), "nth", Some("5"), true)
+            .unwrap();
+        history
+            .record_verification("00", None, "wide", None, false)
+            .unwrap();
+        assert_eq!(
+            verifications(&history),
+            [
+                (Some("factorial".to_string()), "nth".to_string()),
+                (None, "wide".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn lists_proofs_matching_the_query() {
+        let history = history_with(&[("fib", "1"), ("factorial", "2"), ("fib", "3"), ("fib", "4")]);
+        let inputs = |query: ProofQuery| -> Vec<String> {
+            let proofs = history.list_proofs(&query).unwrap();
+            proofs.into_iter().map(|proof| proof.public_input).collect()
+        };
+
+        assert_eq!(inputs(ProofQuery::default()), ["1", "2", "3", "4"]);
+        let fib = || ProofQuery {
+            circuit: Some("fib".to_string()),
+            ..ProofQuery::default()
+        };
+        assert_eq!(inputs(fib()), ["1", "3", "4"]);
+        assert_eq!(
+            inputs(ProofQuery {
+                limit: Some(1),
+                offset: Some(1),
+                ..fib()
+            }),
+            ["3"]
+        );
+        assert!(inputs(ProofQuery {
+            since: Some(i64::MAX),
+            ..ProofQuery::default()
+        })
+        .is_empty());
+    }
+}
@@ -0,0 +1,215 @@
+use crate::error::Error;
+use crate::worker::{Mode, RawFibResult};
+use lru::LruCache;
+use std::future::Future;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+// A proof shared by every request with the same key. It is empty while the
+// first request with the key is still proving, or if that proof failed. The
+// lock is asynchronous, so requests waiting for it hold no proving slot.
+type ProofSlot = Arc<tokio::sync::Mutex<Option<RawFibResult>>>;
+
+// Define a proof remembered under an idempotency key, with the parameters it
+// was requested with.
+struct Entry {
+    a: u64,
+    mode: Mode,
+    proof: ProofSlot,
+    stored_at: Instant,
+}
+
+/// Proofs remembered by the Idempotency-Key they were requested with, so a
+/// retried request returns the same receipt instead of proving again. At most
+/// `max_keys` keys are remembered; beyond that the least recently used key is
+/// forgotten.
+pub struct IdempotencyCache {
+    ttl: Duration,
+    entries: Mutex<LruCache<String, Entry>>,
+}
+
+/// Shared idempotency cache.
+pub type IdempotencyStore = Arc<IdempotencyCache>;
+
+/// Creates an empty idempotency cache whose entries expire after `ttl`.
+pub fn new_store(ttl: Duration, max_keys: NonZeroUsize) -> IdempotencyStore {
+    Arc::new(IdempotencyCache {
+        ttl,
+        entries: Mutex::new(LruCache::new(max_keys)),
+    })
+}
+
+impl IdempotencyCache {
+    /// Returns the proof stored under `key` if there is one, or runs `prove`
+    /// for fib(a) and stores its result. A request that arrives while another
+    /// with the same key is proving waits for that proof instead of starting
+    /// its own. Reusing a key with a different input or mode is an error.
+    /// Failed proofs are not stored, so they can be retried.
+    pub async fn get_or_prove<F, Fut>(
+        &self,
+        key: &str,
+        a: u64,
+        mode: Mode,
+        prove: F,
+    ) -> Result<RawFibResult, Error>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<RawFibResult, Error>>,
+    {
+        let proof = self.entry(key, a, mode)?;
+        let mut proof = proof.lock().await;
+        if let Some(result) = proof.as_ref() {
+            return Ok(result.clone());
+        }
+
+        let result = prove().await?;
+        *proof = Some(result.clone());
+        Ok(result)
+    }
+
+    /// Returns the proof slot for `key`, creating it if the key is new or has
+    /// expired.
+    fn entry(&self, key: &str, a: u64, mode: Mode) -> Result<ProofSlot, Error> {
+        // The map is only read and written under the lock, so it is
+        // consistent even if a holder panicked.
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        match entries.get(key) {
+            Some(entry) if entry.stored_at.elapsed() < self.ttl => {
+                if entry.a != a || entry.mode != mode {
+                    return Err(Error::IdempotencyKeyReused(key.to_string()));
+                }
+                Ok(entry.proof.clone())
+            }
+            _ => {
+                let proof = Arc::new(tokio::sync::Mutex::new(None));
+                entries.put(
+                    key.to_string(),
+                    Entry {
+                        a,
+                        mode,
+                        proof: proof.clone(),
+                        stored_at: Instant::now(),
+                    },
+                );
+                Ok(proof)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn store(ttl: Duration, max_keys: usize) -> IdempotencyStore {
+        new_store(ttl, NonZeroUsize::new(max_keys).unwrap())
+    }
+
+    // A proof of fib(a) with a recognizable receipt.
+    fn proven(a: u64, mode: Mode) -> RawFibResult {
+        RawFibResult {
+            mode,
+            result: 55,
+            sequence: None,
+            nonce: None,
+            journal: vec![1, 2, 3],
+            proof: a.to_be_bytes().to_vec(),
+            generated_at: 0,
+            proving_time_ms: 0,
+            circuit: "fib",
+            prover_version: "test",
+        }
+    }
+
+    // Stores a proof under `key` as if a request had proven it.
+    fn remember(cache: &IdempotencyCache, key: &str, a: u64, mode: Mode) {
+        let proof = cache.entry(key, a, mode).unwrap();
+        *proof.try_lock().unwrap() = Some(proven(a, mode));
+    }
+
+    // Fails the test if it is asked to prove.
+    async fn unreachable_prove() -> Result<RawFibResult, Error> {
+        panic!("The stored proof should have been returned")
+    }
+
+    #[tokio::test]
+    async fn returns_the_stored_proof_for_a_reused_key() {
+        let cache = store(Duration::from_secs(60), 8);
+        remember(&cache, "key", 10, Mode::Nth);
+        let result = cache
+            .get_or_prove("key", 10, Mode::Nth, unreachable_prove)
+            .await
+            .unwrap();
+        assert_eq!(
+            (result.result, result.proof),
+            (55, proven(10, Mode::Nth).proof)
+        );
+    }
+
+    #[tokio::test]
+    async fn proves_once_for_concurrent_requests_with_a_key() {
+        let cache = store(Duration::from_secs(60), 8);
+        let proofs = AtomicUsize::new(0);
+        let prove = |a| {
+            let proofs = &proofs;
+            move || async move {
+                proofs.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                Ok(proven(a, Mode::Nth))
+            }
+        };
+
+        let (first, retry, other) = tokio::join!(
+            cache.get_or_prove("key", 10, Mode::Nth, prove(10)),
+            cache.get_or_prove("key", 10, Mode::Nth, prove(10)),
+            cache.get_or_prove("other", 11, Mode::Nth, prove(11)),
+        );
+        assert_eq!(first.unwrap().proof, retry.unwrap().proof);
+        assert_eq!(other.unwrap().proof, proven(11, Mode::Nth).proof);
+        assert_eq!(proofs.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn retries_a_failed_proof() {
+        let cache = store(Duration::from_secs(60), 8);
+        let failed = cache
+            .get_or_prove("key", 10, Mode::Nth, || async {
+                Err(Error::TaskFailed("task panicked".to_string()))
+            })
+            .await;
+        assert!(matches!(failed, Err(Error::TaskFailed(_))));
+
+        let retried = cache
+            .get_or_prove("key", 10, Mode::Nth, || async { Ok(proven(10, Mode::Nth)) })
+            .await;
+        assert_eq!(retried.unwrap().proof, proven(10, Mode::Nth).proof);
+    }
+
+    #[tokio::test]
+    async fn rejects_a_key_reused_with_other_parameters() {
+        let cache = store(Duration::from_secs(60), 8);
+        remember(&cache, "key", 10, Mode::Nth);
+        let outcome = cache
+            .get_or_prove("key", 11, Mode::Nth, unreachable_prove)
+            .await;
+        assert!(matches!(outcome, Err(Error::IdempotencyKeyReused(key)) if key == "key"));
+        let outcome = cache
+            .get_or_prove("key", 10, Mode::Wide, unreachable_prove)
+            .await;
+        assert!(matches!(outcome, Err(Error::IdempotencyKeyReused(_))));
+    }
+
+    #[test]
+    fn forgets_expired_and_evicted_keys() {
+        let expiring = store(Duration::ZERO, 8);
+        remember(&expiring, "key", 10, Mode::Nth);
+        assert!(expiring.entry("key", 11, Mode::Nth).is_ok());
+
+        let capped = store(Duration::from_secs(60), 1);
+        remember(&capped, "first", 10, Mode::Nth);
+        remember(&capped, "second", 10, Mode::Nth);
+        assert!(capped.entry("first", 11, Mode::Nth).is_ok());
+    }
+}
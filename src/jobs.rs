@@ -0,0 +1,235 @@
+use crate::error::{Error, ErrorResponse};
+use crate::slots;
+use crate::worker::{self, Encoding, FibResult, Mode};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use uuid::Uuid;
+
+// Define the request structure for submitting a proving job.
+#[derive(Deserialize)]
+pub struct JobRequest {
+    pub n: u64,
+    #[serde(default)]
+    pub mode: Mode,
+    #[serde(default)]
+    pub encoding: Encoding,
+}
+
+// Define the response structure returned when a job is submitted.
+#[derive(Serialize)]
+pub struct JobCreated {
+    pub job_id: Uuid,
+}
+
+// Define the lifecycle of a proving job.
+#[derive(Clone, Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum JobState {
+    Pending,
+    Done { result: FibResult },
+    Failed(ErrorResponse),
+}
+
+/// Proves a submitted job on a blocking thread.
+type Prover = dyn Fn(JobRequest) -> Result<FibResult, Error> + Send + Sync;
+
+/// Submitted jobs keyed by job id. Finished jobs are dropped `ttl` after
+/// they complete, and at most `max_jobs` are held at once, pending or not, so
+/// neither memory nor the number of background tasks can grow without bound.
+/// A job that takes longer than `prove_timeout` to prove fails.
+pub struct JobQueue {
+    ttl: Duration,
+    max_jobs: usize,
+    prove_timeout: Duration,
+    prover: Arc<Prover>,
+    jobs: Mutex<HashMap<Uuid, JobState>>,
+}
+
+/// Shared job queue.
+pub type JobStore = Arc<JobQueue>;
+
+/// Creates an empty job queue holding at most `max_jobs` jobs, each kept for
+/// `ttl` after it finishes and given `prove_timeout` to prove.
+pub fn new_store(ttl: Duration, max_jobs: usize, prove_timeout: Duration) -> JobStore {
+    with_prover(ttl, max_jobs, prove_timeout, |request| {
+        worker::phi(request.n, request.mode, request.encoding)
+    })
+}
+
+/// Creates an empty job queue like [`new_store`] that runs jobs through
+/// `prover` instead of the zkVM.
+pub fn with_prover<F>(
+    ttl: Duration,
+    max_jobs: usize,
+    prove_timeout: Duration,
+    prover: F,
+) -> JobStore
+where
+    F: Fn(JobRequest) -> Result<FibResult, Error> + Send + Sync + 'static,
+{
+    Arc::new(JobQueue {
+        ttl,
+        max_jobs,
+        prove_timeout,
+        prover: Arc::new(prover),
+        jobs: Mutex::new(HashMap::new()),
+    })
+}
+
+impl JobQueue {
+    /// Returns the current state of a job, or `None` if it is unknown or has
+    /// expired.
+    pub fn get(&self, job_id: &Uuid) -> Option<JobState> {
+        self.jobs.lock().unwrap().get(job_id).cloned()
+    }
+
+    /// Registers a pending job and proves it on a blocking thread in the
+    /// background once a proving slot is free, recording the outcome when it
+    /// finishes or times out. Fails if the queue is full.
+    pub fn submit(self: &Arc<Self>, request: JobRequest) -> Result<Uuid, Error> {
+        let job_id = Uuid::new_v4();
+        {
+            let mut jobs = self.jobs.lock().unwrap();
+            if jobs.len() >= self.max_jobs {
+                return Err(Error::QueueFull(self.max_jobs));
+            }
+            jobs.insert(job_id, JobState::Pending);
+        }
+
+        let queue = self.clone();
+        let prover = self.prover.clone();
+        tokio::spawn(async move {
+            let outcome = slots::run(queue.prove_timeout, move || prover(request)).await;
+            let state = match outcome {
+                Ok(result) => JobState::Done { result },
+                Err(e) => JobState::Failed(ErrorResponse::from(&e)),
+            };
+            queue.jobs.lock().unwrap().insert(job_id, state);
+
+            // Keep the outcome around long enough to be collected, then free it.
+            tokio::time::sleep(queue.ttl).await;
+            queue.jobs.lock().unwrap().remove(&job_id);
+        });
+
+        Ok(job_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worker::Mode;
+    use std::sync::mpsc;
+
+    // Result reported for a job proven by the test provers.
+    fn proven(request: JobRequest) -> FibResult {
+        FibResult {
+            mode: request.mode,
+            result: 55,
+            sequence: None,
+            nonce: None,
+            journal_hex: String::new(),
+            encoding: request.encoding,
+            proof: "00".to_string(),
+            generated_at: 0,
+            proving_time_ms: 0,
+            circuit: "fib",
+            prover_version: env!("CARGO_PKG_VERSION"),
+        }
+    }
+
+    fn request() -> JobRequest {
+        JobRequest {
+            n: 10,
+            mode: Mode::Nth,
+            encoding: Encoding::Hex,
+        }
+    }
+
+    // A queue whose jobs are held until the returned sender is dropped.
+    fn held_queue(ttl: Duration, max_jobs: usize) -> (JobStore, mpsc::Sender<()>) {
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let release_rx = Mutex::new(release_rx);
+        let queue = with_prover(ttl, max_jobs, Duration::from_secs(60), move |request| {
+            let _ = release_rx.lock().unwrap().recv();
+            Ok(proven(request))
+        });
+        (queue, release_tx)
+    }
+
+    // Polls a job until it is no longer pending.
+    async fn settled(queue: &JobStore, job_id: &Uuid) -> Option<JobState> {
+        let poll = async {
+            loop {
+                match queue.get(job_id) {
+                    Some(JobState::Pending) => tokio::time::sleep(Duration::from_millis(10)).await,
+                    state => return state,
+                }
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(10), poll)
+            .await
+            .expect("The job stayed pending")
+    }
+
+    #[tokio::test]
+    async fn reports_pending_jobs_until_they_are_done() {
+        let (queue, release) = held_queue(Duration::from_secs(60), 8);
+        let job_id = queue.submit(request()).unwrap();
+        assert!(matches!(queue.get(&job_id), Some(JobState::Pending)));
+
+        drop(release);
+        match settled(&queue, &job_id).await {
+            Some(JobState::Done { result }) => assert_eq!(result.result, 55),
+            _ => panic!("expected the job to be done"),
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_failed_jobs() {
+        let queue = with_prover(Duration::from_secs(60), 8, Duration::from_secs(60), |_| {
+            Err(Error::Prove("guest panicked".to_string()))
+        });
+        let job_id = queue.submit(request()).unwrap();
+        let state = settled(&queue, &job_id).await;
+        assert_eq!(
+            serde_json::to_value(state).unwrap(),
+            serde_json::json!({
+                "status": "failed",
+                "error": "Failed to execute guest: guest panicked",
+                "kind": "proving_failed",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn forgets_finished_jobs_after_the_ttl() {
+        let queue = with_prover(
+            Duration::from_millis(50),
+            8,
+            Duration::from_secs(60),
+            |request| Ok(proven(request)),
+        );
+        let job_id = queue.submit(request()).unwrap();
+        assert!(matches!(
+            settled(&queue, &job_id).await,
+            Some(JobState::Done { .. })
+        ));
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(queue.get(&job_id).is_none());
+    }
+
+    #[tokio::test]
+    async fn rejects_jobs_once_the_queue_is_full() {
+        let (queue, release) = held_queue(Duration::from_secs(60), 1);
+        let job_id = queue.submit(request()).unwrap();
+        assert!(matches!(queue.submit(request()), Err(Error::QueueFull(1))));
+
+        drop(release);
+        settled(&queue, &job_id).await;
+        assert!(matches!(queue.submit(request()), Err(Error::QueueFull(1))));
+    }
+}
@@ -0,0 +1,39 @@
+//! Proving and verification of the RISC Zero Fibonacci guest.
+//!
+//! The HTTP server in `main.rs` is a thin layer over this library, so the
+//! same functionality can be used directly from another Rust project:
+//!
+//! ```no_run
+//! use risc_v::{phi, verify, Encoding, Mode, VerifyRequest};
+//!
+//! let result = phi(10, Mode::Nth, Encoding::Hex).unwrap();
+//! let request = VerifyRequest {
+//!     proof: result.proof,
+//!     image_id: "4c2b049124a792ebb4e9cff145c2dbba3219c329fc1407c8dca2b202218900ba".to_string(),
+//!     encoding: Encoding::Hex,
+//!     mode: Some(Mode::Nth),
+//!     nonce: None,
+//! };
+//! assert!(verify(&request).is_valid);
+//! ```
+
+pub mod challenge;
+pub mod config;
+pub mod error;
+pub mod history;
+pub mod idempotency;
+pub mod jobs;
+pub mod logging;
+pub mod metrics;
+pub mod ndjson;
+pub mod openapi;
+pub mod reply;
+pub mod slots;
+pub mod worker;
+pub mod ws;
+
+pub use error::Error;
+pub use worker::{
+    compute, phi, phi_raw, verify, verify_raw, ComputeRequest, ComputeResult, Encoding, FibResult,
+    Mode, Op, RawFibResult, VerifyRequest, VerifyResult,
+};
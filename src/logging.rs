@@ -0,0 +1,250 @@
+use log::{info, Record};
+use std::convert::Infallible;
+use std::env;
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::time::Instant;
+use uuid::Uuid;
+use warp::http::header::HeaderValue;
+use warp::http::{HeaderMap, Method, StatusCode};
+use warp::path::FullPath;
+use warp::reply::Response;
+use warp::{Filter, Reply};
+
+// Default log filter if RUST_LOG is not set: info for this crate, warn for
+// dependencies.
+const DEFAULT_LOG_FILTER: &str = "warn,risc_v=info";
+
+// Log target of the per-request log line, so it can be filtered on its own.
+const ACCESS_LOG: &str = "risc_v::access";
+
+// Header carrying the id used to correlate a request across logs.
+const REQUEST_ID: &str = "x-request-id";
+
+// Define the output formats of the per-request log line.
+#[derive(Clone, Copy)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+// Define the fields of the per-request log line.
+struct RequestLog<'a> {
+    method: &'a Method,
+    path: &'a str,
+    status: StatusCode,
+    elapsed_ms: u128,
+    remote_addr: Option<SocketAddr>,
+    request_id: &'a str,
+}
+
+impl LogFormat {
+    /// Reads the log format from the LOG_FORMAT environment variable,
+    /// defaulting to human-readable text.
+    pub fn from_env() -> Self {
+        match env::var("LOG_FORMAT").as_deref() {
+            Ok("json") => LogFormat::Json,
+            _ => LogFormat::Text,
+        }
+    }
+}
+
+/// Installs the global logger, filtered by RUST_LOG. In JSON format every
+/// line written to stderr is a single JSON object.
+pub fn init(format: LogFormat) {
+    let filters = env::var("RUST_LOG").unwrap_or_else(|_| DEFAULT_LOG_FILTER.to_string());
+    builder(format, &filters).init();
+}
+
+/// Configures a logger with the given `RUST_LOG`-style filters.
+fn builder(format: LogFormat, filters: &str) -> env_logger::Builder {
+    let mut builder = env_logger::Builder::new();
+    builder.parse_filters(filters);
+    if let LogFormat::Json = format {
+        builder.format(write_json);
+    }
+    builder
+}
+
+/// Writes a record as one line of JSON. Request lines are already JSON
+/// objects and are written as they are; other messages are wrapped in an
+/// object with `level`, `target` and `message` keys.
+fn write_json(out: &mut impl Write, record: &Record) -> io::Result<()> {
+    if record.target() == ACCESS_LOG {
+        return writeln!(out, "{}", record.args());
+    }
+    let line = serde_json::json!({
+        "level": record.level().as_str(),
+        "target": record.target(),
+        "message": record.args().to_string(),
+    });
+    writeln!(out, "{}", line)
+}
+
+/// Wraps a filter so that every response carries an X-Request-Id header,
+/// echoing the client's or generating a UUID, and is logged with it.
+pub fn with_request_log<F, T>(
+    format: LogFormat,
+    filter: F,
+) -> impl Filter<Extract = (Response,), Error = Infallible> + Clone
+where
+    F: Filter<Extract = (T,), Error = Infallible> + Clone + Send + Sync + 'static,
+    T: Reply + Send,
+{
+    warp::any()
+        .map(Instant::now)
+        .and(warp::method())
+        .and(warp::path::full())
+        .and(warp::addr::remote())
+        .and(warp::header::headers_cloned())
+        .and(filter)
+        .map(
+            move |started: Instant,
+                  method: Method,
+                  path: FullPath,
+                  remote_addr: Option<SocketAddr>,
+                  headers: HeaderMap,
+                  reply: T| {
+                let request_id = headers
+                    .get(REQUEST_ID)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string)
+                    .unwrap_or_else(|| Uuid::new_v4().to_string());
+                let mut response = reply.into_response();
+                if let Ok(value) = HeaderValue::from_str(&request_id) {
+                    response.headers_mut().insert(REQUEST_ID, value);
+                }
+                log_request(
+                    format,
+                    &RequestLog {
+                        method: &method,
+                        path: path.as_str(),
+                        status: response.status(),
+                        elapsed_ms: started.elapsed().as_millis(),
+                        remote_addr,
+                        request_id: &request_id,
+                    },
+                );
+                response
+            },
+        )
+}
+
+/// Logs one line per request with its method, path, status, elapsed time,
+/// remote address and request id, under the `risc_v::access` target.
+fn log_request(format: LogFormat, request: &RequestLog) {
+    let remote_addr = request
+        .remote_addr
+        .map(|addr| addr.to_string())
+        .unwrap_or_else(|| "-".to_string());
+    match format {
+        LogFormat::Text => info!(
+            target: ACCESS_LOG,
+            "{} {} {} {}ms {} {}",
+            request.method,
+            request.path,
+            request.status.as_u16(),
+            request.elapsed_ms,
+            remote_addr,
+            request.request_id
+        ),
+        LogFormat::Json => info!(
+            target: ACCESS_LOG,
+            "{}",
+            serde_json::json!({
+                "method": request.method.as_str(),
+                "path": request.path,
+                "status": request.status.as_u16(),
+                "elapsed_ms": request.elapsed_ms,
+                "remote_addr": remote_addr,
+                "request_id": request.request_id,
+            })
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::{Level, Log};
+    use std::sync::{Arc, Mutex};
+
+    // Collects the logger's output so a test can read it back.
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn raises_a_single_module_to_debug() {
+        let captured = Captured::default();
+        let logger = builder(LogFormat::Json, "warn,risc_v=info,risc_v::worker=debug")
+            .target(env_logger::Target::Pipe(Box::new(captured.clone())))
+            .build();
+        for target in ["risc_v::worker", ACCESS_LOG, "risc_v", "hyper"] {
+            logger.log(
+                &Record::builder()
+                    .target(target)
+                    .level(Level::Debug)
+                    .args(format_args!("debug from {}", target))
+                    .build(),
+            );
+        }
+        logger.flush();
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        let targets: Vec<String> = output
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .map(|line| line["target"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(targets, ["risc_v::worker"]);
+    }
+
+    #[test]
+    fn writes_each_json_line_as_one_object() {
+        let access = serde_json::json!({ "method": "GET", "path": "/health", "status": 200 });
+        let mut out = Vec::new();
+        write_json(
+            &mut out,
+            &Record::builder()
+                .target(ACCESS_LOG)
+                .level(Level::Info)
+                .args(format_args!("{}", access))
+                .build(),
+        )
+        .unwrap();
+        write_json(
+            &mut out,
+            &Record::builder()
+                .target("risc_v::worker")
+                .level(Level::Warn)
+                .args(format_args!("Fibonacci cache hit for n={}", 5))
+                .build(),
+        )
+        .unwrap();
+
+        let lines: Vec<serde_json::Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines[0], access);
+        assert_eq!(
+            lines[1],
+            serde_json::json!({
+                "level": "WARN",
+                "target": "risc_v::worker",
+                "message": "Fibonacci cache hit for n=5",
+            })
+        );
+    }
+}
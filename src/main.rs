@@ -1,29 +1,1469 @@
-mod worker;
+use clap::{Parser, Subcommand};
+use log::{info, warn};
+use risc_v::config::{env_or, env_secs_or};
+use risc_v::error::ErrorResponse;
+use risc_v::{
+    challenge, history, idempotency, jobs, logging, metrics, ndjson, openapi, reply, slots, worker,
+    ws, Error,
+};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::env;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+use warp::{Filter, Rejection, Reply};
 
-use warp::Filter;
+// Default bind address and port if not provided via environment variables.
+const DEFAULT_BIND_ADDR: &str = "0.0.0.0";
+const DEFAULT_PORT: &str = "3030";
+
+// Default maximum request body size (1 MiB) if not provided via an environment variable.
+const DEFAULT_MAX_BODY_BYTES: &str = "1048576";
+
+// Default maximum proving time if not provided via an environment variable.
+const DEFAULT_PROVE_TIMEOUT_SECS: &str = "60";
+
+// Default lifetime of an Idempotency-Key (24 hours) if not provided via an
+// environment variable.
+const DEFAULT_IDEMPOTENCY_TTL_SECS: &str = "86400";
+
+// Default maximum number of remembered Idempotency-Keys if not provided via an
+// environment variable.
+const DEFAULT_IDEMPOTENCY_MAX_KEYS: &str = "10000";
+
+// Default time a finished job is kept for polling (1 hour) if not provided via
+// an environment variable.
+const DEFAULT_JOB_TTL_SECS: &str = "3600";
+
+// Default maximum number of jobs held at once if not provided via an
+// environment variable.
+const DEFAULT_MAX_JOBS: &str = "1024";
+
+// Command line interface. Running without a subcommand starts the server.
+#[derive(Parser)]
+#[command(version, about = "RISC Zero Fibonacci prover")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the HTTP server.
+    Serve,
+    /// Prove fib(n) and print the result and proof as JSON.
+    Prove {
+        #[arg(long)]
+        n: u64,
+    },
+    /// Verify a hex receipt against an image id and print the outcome as JSON.
+    Verify {
+        #[arg(long)]
+        proof: String,
+        #[arg(long)]
+        image_id: String,
+    },
+}
+
+// Configuration the routes are built from, read from the environment at
+// startup.
+struct Settings {
+    // Maximum accepted request body size for routes with a body.
+    max_body_bytes: u64,
+    // Maximum time a proving route may take before replying 504.
+    prove_timeout: Duration,
+    // Format of the per-request log line.
+    log_format: logging::LogFormat,
+    // Origins allowed to call the API from a browser.
+    cors: warp::cors::Builder,
+    // Responses remembered by Idempotency-Key, so retried requests do not
+    // prove again.
+    idempotency: idempotency::IdempotencyStore,
+    // Proofs submitted to run in the background.
+    jobs: jobs::JobStore,
+    // Set once startup initialization, including the optional warmup proof,
+    // has finished. Proving routes answer 503 until then, so no request races
+    // a half-initialized prover and readiness probes hold traffic back.
+    ready: Arc<AtomicBool>,
+}
+
+impl Settings {
+    /// Reads the settings from the environment, failing fast if a value is
+    /// invalid. The server starts out not ready.
+    fn from_env() -> Self {
+        let prove_timeout = env_secs_or("PROVE_TIMEOUT_SECS", DEFAULT_PROVE_TIMEOUT_SECS);
+        Settings {
+            max_body_bytes: env_or("MAX_BODY_BYTES", DEFAULT_MAX_BODY_BYTES),
+            prove_timeout,
+            log_format: logging::LogFormat::from_env(),
+            cors: get_cors(),
+            idempotency: idempotency::new_store(
+                env_secs_or("IDEMPOTENCY_TTL_SECS", DEFAULT_IDEMPOTENCY_TTL_SECS),
+                env_or("IDEMPOTENCY_MAX_KEYS", DEFAULT_IDEMPOTENCY_MAX_KEYS),
+            ),
+            jobs: jobs::new_store(
+                env_secs_or("JOB_TTL_SECS", DEFAULT_JOB_TTL_SECS),
+                env_or("MAX_JOBS", DEFAULT_MAX_JOBS),
+                prove_timeout,
+            ),
+            ready: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+// Rejection for proving requests received before the server is ready.
+#[derive(Debug)]
+struct NotReady;
+
+impl warp::reject::Reject for NotReady {}
+
+// Rejection for a JSON body that does not match the request type, naming the
+// field at fault.
+#[derive(Debug)]
+struct InvalidBody(String);
+
+impl warp::reject::Reject for InvalidBody {}
+
+// Limits enforced by the server, reported by GET /limits. Built from the same
+// values the routes enforce, so the two cannot drift apart.
+#[derive(Clone, Copy, Serialize)]
+struct Limits {
+    max_body_bytes: u64,
+    prove_timeout_secs: u64,
+    min_n: u64,
+    max_fib_n: u64,
+    max_fib_n_wide: u64,
+    max_fib_n_sequence: u64,
+    max_factorial_n: u64,
+    max_concurrent_proofs: usize,
+}
+
+// Query parameters accepted by the Fibonacci route.
+#[derive(Deserialize)]
+struct FibQuery {
+    #[serde(default)]
+    mode: worker::Mode,
+    #[serde(default)]
+    encoding: worker::Encoding,
+    nonce: Option<String>,
+}
+
+// Query parameters accepted by the raw Fibonacci route.
+#[derive(Deserialize)]
+struct ModeQuery {
+    #[serde(default)]
+    mode: worker::Mode,
+}
+
+// Query parameter accepted by every route.
+#[derive(Deserialize)]
+struct CanonicalQuery {
+    #[serde(default)]
+    canonical: bool,
+}
+
+// Query parameters accepted by the raw verification route.
+#[derive(Deserialize)]
+struct VerifyQuery {
+    #[serde(default)]
+    mode: Option<worker::Mode>,
+}
+
+// Query parameters accepted by the readiness route.
+#[derive(Deserialize)]
+struct ReadinessQuery {
+    #[serde(default)]
+    deep: bool,
+}
+
+/// Builds the socket address to listen on from the BIND_ADDR and PORT
+/// environment variables, failing fast if either value is invalid.
+fn get_bind_address() -> SocketAddr {
+    let ip: IpAddr = env_or("BIND_ADDR", DEFAULT_BIND_ADDR);
+    SocketAddr::new(ip, env_or("PORT", DEFAULT_PORT))
+}
+
+/// Builds the CORS policy from the comma-separated CORS_ALLOWED_ORIGINS
+/// environment variable, failing fast if an origin is invalid. Any origin is
+/// allowed when the variable is unset, with a warning.
+fn get_cors() -> warp::cors::Builder {
+    let origins = env::var("CORS_ALLOWED_ORIGINS").ok();
+    if origins.is_none() {
+        warn!("CORS_ALLOWED_ORIGINS is unset; allowing any origin");
+    }
+    cors_policy(origins.as_deref())
+}
+
+/// Builds the CORS policy allowing the comma-separated `origins`, or any
+/// origin if `None`.
+fn cors_policy(origins: Option<&str>) -> warp::cors::Builder {
+    let cors = warp::cors()
+        .allow_methods(vec!["GET", "POST"])
+        .allow_headers(vec![
+            "content-type",
+            "accept",
+            "idempotency-key",
+            "x-image-id",
+            "x-request-id",
+        ])
+        .expose_headers(vec!["x-request-id", "x-input", "x-result", "x-journal"]);
+    match origins {
+        Some(origins) => cors.allow_origins(
+            origins
+                .split(',')
+                .map(str::trim)
+                .filter(|origin| !origin.is_empty()),
+        ),
+        None => cors.allow_any_origin(),
+    }
+}
+
+/// Filter that rejects requests with [`NotReady`] until `ready` is set.
+fn ready_gate(ready: Arc<AtomicBool>) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::any()
+        .and_then(move || {
+            let ready = ready.load(Ordering::Acquire);
+            async move {
+                if ready {
+                    Ok(())
+                } else {
+                    Err(warp::reject::custom(NotReady))
+                }
+            }
+        })
+        .untuple_one()
+}
+
+/// Decodes a JSON request body like `warp::body::json`. A body that is valid
+/// JSON but does not match the request type is rejected with [`InvalidBody`],
+/// naming the missing or mistyped field.
+fn json_body<T: DeserializeOwned + Send>() -> impl Filter<Extract = (T,), Error = Rejection> + Copy
+{
+    warp::body::json().and_then(|body: serde_json::Value| async move {
+        serde_path_to_error::deserialize(body).map_err(|e| {
+            let path = e.path().to_string();
+            let message = if path == "." {
+                e.inner().to_string()
+            } else {
+                format!("{} at `{}`", e.inner(), path)
+            };
+            warp::reject::custom(InvalidBody(message))
+        })
+    })
+}
+
+/// Converts rejections into JSON error replies with a matching status, so
+/// every response passes through the request log and carries a request id.
+async fn handle_rejection(
+    err: Rejection,
+) -> Result<warp::reply::WithStatus<warp::reply::Json>, Infallible> {
+    let (kind, message, status) = if err.find::<NotReady>().is_some() {
+        (
+            "not_ready",
+            "Server is starting up; retry shortly".to_string(),
+            warp::http::StatusCode::SERVICE_UNAVAILABLE,
+        )
+    } else if err.find::<warp::reject::PayloadTooLarge>().is_some() {
+        (
+            "payload_too_large",
+            "Request body too large".to_string(),
+            warp::http::StatusCode::PAYLOAD_TOO_LARGE,
+        )
+    } else if let Some(e) = err.find::<warp::filters::body::BodyDeserializeError>() {
+        // Report where the body stops being valid JSON, as located by serde.
+        let cause = std::error::Error::source(e).map_or_else(|| e.to_string(), |c| c.to_string());
+        (
+            "invalid_body",
+            format!("Invalid request body: {}", cause),
+            warp::http::StatusCode::UNPROCESSABLE_ENTITY,
+        )
+    } else if let Some(InvalidBody(cause)) = err.find() {
+        (
+            "invalid_body",
+            format!("Invalid request body: {}", cause),
+            warp::http::StatusCode::UNPROCESSABLE_ENTITY,
+        )
+    } else if err.find::<warp::reject::UnsupportedMediaType>().is_some() {
+        // warp::body::json() rejects bodies sent with a non-JSON Content-Type,
+        // while still accepting requests that omit the header.
+        (
+            "unsupported_media_type",
+            "Unsupported content type: send the body as application/json".to_string(),
+            warp::http::StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        )
+    } else if err.is_not_found() {
+        // Mirror warp's default statuses for the remaining rejections.
+        (
+            "not_found",
+            "Not found".to_string(),
+            warp::http::StatusCode::NOT_FOUND,
+        )
+    } else if let Some(e) = err.find::<warp::reject::MethodNotAllowed>() {
+        (
+            "method_not_allowed",
+            e.to_string(),
+            warp::http::StatusCode::METHOD_NOT_ALLOWED,
+        )
+    } else if let Some(e) = err.find::<warp::reject::InvalidQuery>() {
+        (
+            "invalid_query",
+            e.to_string(),
+            warp::http::StatusCode::BAD_REQUEST,
+        )
+    } else if let Some(e) = err.find::<warp::reject::MissingHeader>() {
+        (
+            "invalid_header",
+            e.to_string(),
+            warp::http::StatusCode::BAD_REQUEST,
+        )
+    } else if let Some(e) = err.find::<warp::reject::InvalidHeader>() {
+        (
+            "invalid_header",
+            e.to_string(),
+            warp::http::StatusCode::BAD_REQUEST,
+        )
+    } else if let Some(e) = err.find::<warp::reject::LengthRequired>() {
+        (
+            "length_required",
+            e.to_string(),
+            warp::http::StatusCode::LENGTH_REQUIRED,
+        )
+    } else if let Some(e) = err.find::<warp::cors::CorsForbidden>() {
+        (
+            "forbidden",
+            e.to_string(),
+            warp::http::StatusCode::FORBIDDEN,
+        )
+    } else {
+        (
+            "internal",
+            format!("Unhandled rejection: {:?}", err),
+            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    };
+    Ok(warp::reply::with_status(
+        warp::reply::json(&ErrorResponse::new(kind, message)),
+        status,
+    ))
+}
+
+/// Status for a failed proof: 503 when no prover backend is available, so
+/// clients can tell a misconfigured server from a failed proof, 422 when an
+/// Idempotency-Key is reused with different parameters, 400 for an invalid
+/// challenge nonce, 504 when proving timed out, 500 otherwise.
+fn error_status(e: &Error) -> warp::http::StatusCode {
+    match e {
+        Error::ProverUnavailable(_) => warp::http::StatusCode::SERVICE_UNAVAILABLE,
+        Error::IdempotencyKeyReused(_) => warp::http::StatusCode::UNPROCESSABLE_ENTITY,
+        Error::QueueFull(_) => warp::http::StatusCode::SERVICE_UNAVAILABLE,
+        Error::Challenge(_) => warp::http::StatusCode::BAD_REQUEST,
+        Error::TooManyChallenges(_) => warp::http::StatusCode::SERVICE_UNAVAILABLE,
+        Error::Timeout(_) => warp::http::StatusCode::GATEWAY_TIMEOUT,
+        _ => warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Reply for history routes when no DB_PATH is configured.
+fn history_disabled() -> warp::reply::WithStatus<warp::reply::Json> {
+    warp::reply::with_status(
+        warp::reply::json(&ErrorResponse::new(
+            "history_disabled",
+            "Proof history is disabled: set DB_PATH to enable it",
+        )),
+        warp::http::StatusCode::NOT_FOUND,
+    )
+}
+
+/// Compares the embedded guest ELF's image id with the configured IMAGE_ID,
+/// warning on mismatch. Set STRICT_IMAGE_ID=1 to refuse to start instead.
+fn check_image_id() {
+    let strict = env::var("STRICT_IMAGE_ID").is_ok_and(|v| v == "1" || v == "true");
+    let problem = match worker::image_id_info() {
+        Ok(info) if info.matches => return,
+        Ok(info) => format!(
+            "IMAGE_ID mismatch: configured {} but the embedded guest ELF has {}",
+            info.configured, info.computed
+        ),
+        Err(e) => e.to_string(),
+    };
+    if strict {
+        panic!("{}", problem);
+    }
+    warn!("{}; every proof will fail verification", problem);
+}
+
+/// Runs one full proof and verification on a blocking thread when WARMUP=1,
+/// so the first client request does not pay for loading the prover. Runs
+/// while the server is already listening, behind the readiness gate.
+async fn warm_up() {
+    if !env::var("WARMUP").is_ok_and(|v| v == "1" || v == "true") {
+        return;
+    }
+    info!("Warming up the prover");
+    match tokio::task::spawn_blocking(|| worker::selftest(1)).await {
+        Ok(Ok(result)) if result.is_valid => info!("Prover ready"),
+        Ok(Ok(_)) => warn!("Warmup proof failed verification"),
+        Ok(Err(e)) => warn!("Warmup proof failed: {}", e),
+        Err(e) => warn!("Warmup task failed: {}", e),
+    }
+}
+
+/// Runs a blocking handler, such as a proof, on the blocking thread pool so
+/// the event loop stays responsive to other requests while it runs. The
+/// handler waits for a free proving slot first, bounding how many proofs run
+/// at once. Replies 504 if the handler does not finish within the timeout,
+/// including time spent waiting for a slot. The blocking thread cannot be
+/// interrupted, so a timed-out proof is left to finish in the background,
+/// holding its slot, and its result is discarded.
+async fn offload<F, R>(timeout: Duration, handler: F) -> Result<warp::reply::Response, Rejection>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Reply + Send + 'static,
+{
+    let response = match slots::run(timeout, move || Ok(handler())).await {
+        Ok(reply) => reply.into_response(),
+        Err(e) => warp::reply::with_status(
+            warp::reply::json(&ErrorResponse::from(&e)),
+            error_status(&e),
+        )
+        .into_response(),
+    };
+    Ok(response)
+}
+
+/// Runs a verification on the blocking thread pool, so checking a receipt
+/// does not stall the event loop, and replies in the negotiated format.
+/// Verifying is cheap next to proving, so it does not wait for a proving slot.
+async fn verify_offloaded<F>(
+    accept: Option<String>,
+    verify: F,
+) -> Result<warp::reply::Response, Rejection>
+where
+    F: FnOnce() -> worker::VerifyResult + Send + 'static,
+{
+    let response = match tokio::task::spawn_blocking(verify).await {
+        Ok(result) => reply::negotiated(accept.as_deref(), &result, warp::http::StatusCode::OK),
+        Err(e) => warp::reply::with_status(
+            warp::reply::json(&ErrorResponse {
+                error: format!("Verification task failed: {}", e),
+                kind: "verification_failed",
+            }),
+            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+        )
+        .into_response(),
+    };
+    Ok(response)
+}
+
+/// Resolves when the process receives SIGINT (Ctrl+C) or, on Unix, SIGTERM.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    info!("Server shutting down");
+}
 
 #[tokio::main]
 async fn main() {
-    // Health check route.
-    let health = warp::path("health").map(|| warp::reply::html("OK"));
-
-    // Fibonacci route: POST /fib/<a>
-    let phi_route = warp::path!("fib" / u64).and(warp::post()).map(|a: u64| {
-        // Wrap both success and error in a reply with a status.
-        match worker::phi(a) {
-            Ok(result) => {
-                warp::reply::with_status(warp::reply::json(&result), warp::http::StatusCode::OK)
-            }
-            Err(e) => warp::reply::with_status(
-                warp::reply::json(&e),
-                warp::http::StatusCode::INTERNAL_SERVER_ERROR,
-            ),
+    logging::init(logging::LogFormat::from_env());
+
+    let cli = Cli::parse();
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve().await,
+        Command::Prove { n } => {
+            match worker::phi(n, worker::Mode::default(), worker::Encoding::default()) {
+                Ok(result) => println!("{}", serde_json::to_string(&result).unwrap()),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
         }
+        Command::Verify { proof, image_id } => {
+            let request = worker::VerifyRequest {
+                proof,
+                image_id,
+                encoding: worker::Encoding::default(),
+                mode: None,
+                nonce: None,
+            };
+            let result = worker::verify(&request);
+            println!("{}", serde_json::to_string(&result).unwrap());
+            if !result.is_valid {
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Runs the HTTP server until a shutdown signal is received.
+async fn serve() {
+    // Catch a stale IMAGE_ID before serving any request.
+    check_image_id();
+
+    // Open the proof history now so a bad DB_PATH fails fast.
+    history::history();
+
+    // Read CHALLENGE_TTL_SECS now so an invalid value fails fast.
+    challenge::challenges();
+
+    // Read FIB_CACHE_SIZE now so an invalid value fails fast.
+    worker::fib_cache_size();
+
+    let settings = Settings::from_env();
+    let ready = settings.ready.clone();
+
+    // Start the server on the configured address. In-flight requests are
+    // allowed to complete once a shutdown signal is received.
+    let (_, server) = warp::serve(routes(settings))
+        .bind_with_graceful_shutdown(get_bind_address(), shutdown_signal());
+
+    // The listener is bound, so health checks are answered while the prover
+    // warms up. Open the proving routes once it is warm.
+    tokio::spawn(async move {
+        warm_up().await;
+        ready.store(true, Ordering::Release);
     });
+    server.await;
+}
+
+/// Builds the routes served by [`serve`], with the rejection handling, CORS
+/// policy and request log applied.
+fn routes(
+    settings: Settings,
+) -> impl Filter<Extract = (warp::reply::Response,), Error = Infallible> + Clone {
+    let Settings {
+        max_body_bytes,
+        prove_timeout,
+        log_format,
+        cors,
+        idempotency,
+        jobs,
+        ready,
+    } = settings;
+
+    // Health check route.
+    let health = warp::path!("health").map(|| warp::reply::html("OK"));
+
+    // Liveness route: the process is up and serving requests.
+    let health_live = warp::path!("health" / "live").map(|| warp::reply::html("OK"));
+
+    // Readiness route: the service is able to prove and verify. Pass
+    // `?deep=true` to run a full proof round trip.
+    let health_ready = warp::path!("health" / "ready")
+        .and(ready_gate(ready.clone()))
+        .and(warp::query::<ReadinessQuery>())
+        .and_then(move |query: ReadinessQuery| async move {
+            let readiness = move || match worker::check_ready(query.deep) {
+                Ok(()) => {
+                    warp::reply::with_status(warp::reply::json(&"OK"), warp::http::StatusCode::OK)
+                }
+                Err(e) => warp::reply::with_status(
+                    warp::reply::json(&ErrorResponse::from(&e)),
+                    warp::http::StatusCode::SERVICE_UNAVAILABLE,
+                ),
+            };
+            // Only the deep check proves, so only it waits for a proving slot.
+            if query.deep {
+                offload(prove_timeout, readiness).await
+            } else {
+                Ok(readiness().into_response())
+            }
+        });
+
+    // Image id route: compares the configured and embedded guest image ids.
+    let image_id_route =
+        warp::path!("image-id")
+            .and(warp::get())
+            .map(|| match worker::image_id_info() {
+                Ok(info) => {
+                    warp::reply::with_status(warp::reply::json(&info), warp::http::StatusCode::OK)
+                }
+                Err(e) => warp::reply::with_status(
+                    warp::reply::json(&ErrorResponse::from(&e)),
+                    warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+                ),
+            });
+
+    // Version route: GET /version reports which build is deployed.
+    let version_route =
+        warp::path!("version")
+            .and(warp::get())
+            .map(|| match worker::version_info() {
+                Ok(info) => {
+                    warp::reply::with_status(warp::reply::json(&info), warp::http::StatusCode::OK)
+                }
+                Err(e) => warp::reply::with_status(
+                    warp::reply::json(&ErrorResponse::from(&e)),
+                    warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+                ),
+            });
+
+    // Limits route: GET /limits reports the limits enforced by this server.
+    let limits = Limits {
+        max_body_bytes,
+        prove_timeout_secs: prove_timeout.as_secs(),
+        min_n: worker::MIN_FIB_N,
+        max_fib_n: worker::Mode::Nth.max_n(),
+        max_fib_n_wide: worker::Mode::Wide.max_n(),
+        max_fib_n_sequence: worker::Mode::Sequence.max_n(),
+        max_factorial_n: worker::Op::Factorial.max_n(),
+        max_concurrent_proofs: slots::max_concurrent_proofs(),
+    };
+    let limits_route = warp::path!("limits")
+        .and(warp::get())
+        .map(move || warp::reply::json(&limits));
+
+    // OpenAPI route: GET /openapi.json describes the API for clients and SDK
+    // generators.
+    let openapi_route = warp::path!("openapi.json")
+        .and(warp::get())
+        .map(|| warp::reply::json(&openapi::spec()));
+
+    // Metrics route: GET /metrics exposes prover metrics for Prometheus.
+    let metrics_route = warp::path!("metrics").and(warp::get()).map(|| {
+        warp::reply::with_header(
+            metrics::metrics().render(),
+            "content-type",
+            "text/plain; version=0.0.4",
+        )
+    });
+
+    // Responses remembered by Idempotency-Key, so retried requests do not
+    // prove again.
+    let idempotency_filter = warp::any().map(move || idempotency.clone());
+
+    // Challenge route: GET /challenge issues a single-use nonce to pass to
+    // POST /fib/<a>?nonce=<nonce>, binding the proof to this request.
+    let challenge_route =
+        warp::path!("challenge")
+            .and(warp::get())
+            .map(|| match challenge::challenges().issue() {
+                Ok(challenge) => warp::reply::with_status(
+                    warp::reply::json(&challenge),
+                    warp::http::StatusCode::OK,
+                ),
+                Err(e) => warp::reply::with_status(
+                    warp::reply::json(&ErrorResponse::from(&e)),
+                    error_status(&e),
+                ),
+            });
+
+    // Fibonacci route: POST /fib/<a>?mode=<nth|wide>&encoding=<hex|base64>. Send
+    // `Accept: application/cbor` to get a CBOR body with the raw receipt bytes.
+    let phi_route = warp::path!("fib" / u64)
+        .and(warp::post())
+        .and(ready_gate(ready.clone()))
+        .and(warp::query::<FibQuery>())
+        .and(warp::header::optional::<String>("accept"))
+        .and(warp::header::optional::<String>("idempotency-key"))
+        .and(idempotency_filter)
+        .and_then(
+            move |a: u64,
+                  query: FibQuery,
+                  accept: Option<String>,
+                  idempotency_key: Option<String>,
+                  idempotency: idempotency::IdempotencyStore| async move {
+                // Reject out-of-range inputs before taking a proving slot.
+                if let Err(e) = worker::validate_input(a, query.mode) {
+                    return Ok(reply::negotiated(
+                        accept.as_deref(),
+                        &ErrorResponse::from(&e),
+                        warp::http::StatusCode::BAD_REQUEST,
+                    ));
+                }
+
+                // A challenged proof is fresh by design. Otherwise, a retried
+                // request with the same Idempotency-Key gets the stored
+                // receipt instead of a new proof, waiting without a proving
+                // slot while the first request with the key proves.
+                let mode = query.mode;
+                let raw = slots::within(prove_timeout, async {
+                    match (query.nonce, idempotency_key) {
+                        (Some(nonce), _) => {
+                            slots::in_slot(move || worker::phi_raw_challenged(a, mode, &nonce))
+                                .await
+                        }
+                        (None, Some(key)) => {
+                            idempotency
+                                .get_or_prove(&key, a, mode, || {
+                                    slots::in_slot(move || worker::phi_raw(a, mode))
+                                })
+                                .await
+                        }
+                        (None, None) => slots::in_slot(move || worker::phi_raw(a, mode)).await,
+                    }
+                })
+                .await;
+
+                // Wrap both success and error in a reply with a status.
+                let accept = accept.as_deref();
+                Ok::<_, Rejection>(match raw {
+                    Ok(raw) if reply::wants_cbor(accept) => {
+                        reply::cbor(&raw, warp::http::StatusCode::OK)
+                    }
+                    Ok(raw) => reply::negotiated(
+                        accept,
+                        &raw.encode(query.encoding),
+                        warp::http::StatusCode::OK,
+                    ),
+                    Err(e) => reply::negotiated(accept, &ErrorResponse::from(&e), error_status(&e)),
+                })
+            },
+        );
+
+    // Raw Fibonacci route: POST /fib/<a>/raw?mode=<nth|wide|sequence> returns
+    // the bincode receipt as application/octet-stream, with the input, result
+    // and hex journal in the X-Input, X-Result and X-Journal headers.
+    let phi_raw_route = warp::path!("fib" / u64 / "raw")
+        .and(warp::post())
+        .and(ready_gate(ready.clone()))
+        .and(warp::query::<ModeQuery>())
+        .and_then(move |a: u64, query: ModeQuery| async move {
+            // Reject out-of-range inputs before taking a proving slot.
+            if let Err(e) = worker::validate_input(a, query.mode) {
+                return Ok(warp::reply::with_status(
+                    warp::reply::json(&ErrorResponse::from(&e)),
+                    warp::http::StatusCode::BAD_REQUEST,
+                )
+                .into_response());
+            }
+
+            offload(prove_timeout, move || {
+                match worker::phi_raw(a, query.mode) {
+                    Ok(raw) => reply::octet_stream(
+                        raw.proof,
+                        &[
+                            ("x-input", a.to_string()),
+                            ("x-result", raw.result.to_string()),
+                            ("x-journal", hex::encode(&raw.journal)),
+                        ],
+                    ),
+                    Err(e) => warp::reply::with_status(
+                        warp::reply::json(&ErrorResponse::from(&e)),
+                        error_status(&e),
+                    )
+                    .into_response(),
+                }
+            })
+            .await
+        });
+
+    // Compute route: POST /compute with {"op": "fib" | "factorial", "n": ..}
+    // proves the selected operation with the same guest.
+    let compute_route = warp::path!("compute")
+        .and(warp::post())
+        .and(ready_gate(ready.clone()))
+        .and(warp::body::content_length_limit(max_body_bytes))
+        .and(json_body())
+        .and_then(move |request: worker::ComputeRequest| async move {
+            // Reject out-of-range inputs before taking a proving slot.
+            if let Err(e) = worker::validate_compute(&request) {
+                return Ok(warp::reply::with_status(
+                    warp::reply::json(&ErrorResponse::from(&e)),
+                    warp::http::StatusCode::BAD_REQUEST,
+                )
+                .into_response());
+            }
+
+            offload(prove_timeout, move || match worker::compute(&request) {
+                Ok(result) => {
+                    warp::reply::with_status(warp::reply::json(&result), warp::http::StatusCode::OK)
+                }
+                Err(e) => warp::reply::with_status(
+                    warp::reply::json(&ErrorResponse::from(&e)),
+                    error_status(&e),
+                ),
+            })
+            .await
+        });
+
+    // Self-test route: POST /selftest/<a> proves and verifies in one call.
+    let selftest_route = warp::path!("selftest" / u64)
+        .and(warp::post())
+        .and(ready_gate(ready.clone()))
+        .and_then(move |a: u64| async move {
+            // Reject out-of-range inputs before taking a proving slot.
+            if let Err(e) = worker::validate_input(a, worker::Mode::Nth) {
+                return Ok(warp::reply::with_status(
+                    warp::reply::json(&ErrorResponse::from(&e)),
+                    warp::http::StatusCode::BAD_REQUEST,
+                )
+                .into_response());
+            }
+
+            offload(prove_timeout, move || match worker::selftest(a) {
+                Ok(result) => {
+                    warp::reply::with_status(warp::reply::json(&result), warp::http::StatusCode::OK)
+                }
+                Err(e) => warp::reply::with_status(
+                    warp::reply::json(&ErrorResponse::from(&e)),
+                    error_status(&e),
+                ),
+            })
+            .await
+        });
+
+    // History routes: GET /proofs/<id> looks up a recorded proof and
+    // GET /proofs?circuit=&since=&limit=&offset= lists them. Only available
+    // with DB_PATH set.
+    let proof_route =
+        warp::path!("proofs" / i64)
+            .and(warp::get())
+            .map(|id: i64| match history::history() {
+                Some(history) => match history.get_proof(id) {
+                    Ok(Some(record)) => warp::reply::with_status(
+                        warp::reply::json(&record),
+                        warp::http::StatusCode::OK,
+                    ),
+                    Ok(None) => warp::reply::with_status(
+                        warp::reply::json(&ErrorResponse::new(
+                            "not_found",
+                            format!("Proof not found: {}", id),
+                        )),
+                        warp::http::StatusCode::NOT_FOUND,
+                    ),
+                    Err(e) => warp::reply::with_status(
+                        warp::reply::json(&ErrorResponse::from(&e)),
+                        warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+                    ),
+                },
+                None => history_disabled(),
+            });
+
+    let proofs_route = warp::path!("proofs")
+        .and(warp::get())
+        .and(warp::query::<history::ProofQuery>())
+        .map(|query: history::ProofQuery| match history::history() {
+            Some(history) => match history.list_proofs(&query) {
+                Ok(records) => warp::reply::with_status(
+                    warp::reply::json(&records),
+                    warp::http::StatusCode::OK,
+                ),
+                Err(e) => warp::reply::with_status(
+                    warp::reply::json(&ErrorResponse::from(&e)),
+                    warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+                ),
+            },
+            None => history_disabled(),
+        });
+
+    // Job routes: POST /jobs submits a proof in the background and
+    // GET /jobs/<id> polls its status.
+    let jobs_filter = warp::any().map(move || jobs.clone());
+
+    let submit_job_route = warp::path!("jobs")
+        .and(warp::post())
+        .and(ready_gate(ready.clone()))
+        .and(warp::body::content_length_limit(max_body_bytes))
+        .and(json_body())
+        .and(jobs_filter.clone())
+        .map(|request: jobs::JobRequest, jobs: jobs::JobStore| {
+            // Reject out-of-range inputs before queueing the job.
+            if let Err(e) = worker::validate_input(request.n, request.mode) {
+                return warp::reply::with_status(
+                    warp::reply::json(&ErrorResponse::from(&e)),
+                    warp::http::StatusCode::BAD_REQUEST,
+                );
+            }
+            match jobs.submit(request) {
+                Ok(job_id) => warp::reply::with_status(
+                    warp::reply::json(&jobs::JobCreated { job_id }),
+                    warp::http::StatusCode::ACCEPTED,
+                ),
+                Err(e) => warp::reply::with_status(
+                    warp::reply::json(&ErrorResponse::from(&e)),
+                    error_status(&e),
+                ),
+            }
+        });
+
+    let job_status_route = warp::path!("jobs" / Uuid)
+        .and(warp::get())
+        .and(jobs_filter)
+        .map(
+            |job_id: Uuid, jobs: jobs::JobStore| match jobs.get(&job_id) {
+                Some(state) => {
+                    warp::reply::with_status(warp::reply::json(&state), warp::http::StatusCode::OK)
+                }
+                None => warp::reply::with_status(
+                    warp::reply::json(&ErrorResponse::new(
+                        "not_found",
+                        format!("Job not found: {}", job_id),
+                    )),
+                    warp::http::StatusCode::NOT_FOUND,
+                ),
+            },
+        );
+
+    // WebSocket route: GET /ws/fib streams proving progress for one request.
+    let ws_route = warp::path!("ws" / "fib")
+        .and(ready_gate(ready.clone()))
+        .and(warp::ws())
+        .map(move |ws: warp::ws::Ws| {
+            ws.on_upgrade(move |socket| ws::handle_socket(socket, prove_timeout))
+        });
+
+    // Verify route: POST /verify with an encoded receipt and image id.
+    let verify_route = warp::path!("verify")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(max_body_bytes))
+        .and(json_body())
+        .and(warp::header::optional::<String>("accept"))
+        .and_then(|request: worker::VerifyRequest, accept: Option<String>| {
+            verify_offloaded(accept, move || worker::verify(&request))
+        });
+
+    // Raw verification route: POST /verify/raw?mode=<nth|wide|sequence> takes
+    // the bincode receipt as the request body and the image id in the
+    // X-Image-Id header.
+    let verify_raw_route = warp::path!("verify" / "raw")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(max_body_bytes))
+        .and(warp::body::bytes())
+        .and(warp::header::<String>("x-image-id"))
+        .and(warp::query::<VerifyQuery>())
+        .and(warp::header::optional::<String>("accept"))
+        .and_then(
+            |body: warp::hyper::body::Bytes,
+             image_id: String,
+             query: VerifyQuery,
+             accept: Option<String>| {
+                verify_offloaded(accept, move || {
+                    worker::verify_raw(&body, &image_id, query.mode)
+                })
+            },
+        );
+
+    // Streaming verification route: POST /verify/stream takes newline-delimited
+    // verify requests and streams back one result line per request, so memory
+    // stays flat however many receipts are sent. Each line is limited to the
+    // maximum body size.
+    let verify_stream_route = warp::path!("verify" / "stream")
+        .and(warp::post())
+        .and(warp::body::stream())
+        .map(move |body| {
+            let results = ndjson::verify_stream(body, max_body_bytes as usize);
+            warp::reply::with_header(
+                warp::reply::Response::new(warp::hyper::Body::wrap_stream(results)),
+                "content-type",
+                ndjson::NDJSON,
+            )
+        });
 
     // Combine the routes.
-    let routes = health.or(phi_route);
+    let routes = health
+        .or(health_live)
+        .or(health_ready)
+        .or(image_id_route)
+        .or(version_route)
+        .or(metrics_route)
+        .or(limits_route)
+        .or(openapi_route)
+        .or(challenge_route)
+        .or(phi_route)
+        .or(phi_raw_route)
+        .or(compute_route)
+        .or(selftest_route)
+        .or(verify_route)
+        .or(verify_raw_route)
+        .or(verify_stream_route)
+        .or(submit_job_route)
+        .or(job_status_route)
+        .or(ws_route)
+        .or(proof_route)
+        .or(proofs_route);
+
+    // Pass `?canonical=true` to any route to get JSON with sorted keys and no
+    // whitespace, so identical responses hash identically.
+    let routes = warp::query::<CanonicalQuery>()
+        .and(routes)
+        .and_then(|query: CanonicalQuery, reply| reply::canonical(query.canonical, reply))
+        .recover(handle_rejection)
+        .with(cors)
+        .recover(handle_rejection);
+
+    // Tag every response with a request id and log it.
+    logging::with_request_log(log_format, routes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::num::NonZeroUsize;
+
+    // Paths served by `routes`.
+    const PATHS: &[&str] = &[
+        "/health",
+        "/health/live",
+        "/health/ready",
+        "/image-id",
+        "/version",
+        "/limits",
+        "/metrics",
+        "/openapi.json",
+        "/challenge",
+        "/fib/{n}",
+        "/fib/{n}/raw",
+        "/compute",
+        "/selftest/{n}",
+        "/proofs",
+        "/proofs/{id}",
+        "/jobs",
+        "/jobs/{id}",
+        "/ws/fib",
+        "/verify",
+        "/verify/raw",
+        "/verify/stream",
+    ];
+
+    // Settings for the routes under test: small limits, any origin, and a
+    // server that is ready.
+    fn settings() -> Settings {
+        Settings {
+            max_body_bytes: 1024,
+            prove_timeout: Duration::from_secs(60),
+            log_format: logging::LogFormat::Text,
+            cors: warp::cors().allow_any_origin(),
+            idempotency: idempotency::new_store(
+                Duration::from_secs(60),
+                NonZeroUsize::new(8).unwrap(),
+            ),
+            jobs: jobs::new_store(Duration::from_secs(60), 8, Duration::from_secs(60)),
+            ready: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    #[tokio::test]
+    async fn documents_every_route_with_its_statuses() {
+        let routes = routes(settings());
+        let response = warp::test::request()
+            .path("/openapi.json")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), warp::http::StatusCode::OK);
+        let spec: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+
+        let paths = spec["paths"].as_object().unwrap();
+        let mut documented: Vec<&str> = paths.keys().map(String::as_str).collect();
+        let mut served = PATHS.to_vec();
+        documented.sort_unstable();
+        served.sort_unstable();
+        assert_eq!(documented, served);
+
+        for (path, operations) in paths {
+            for (method, operation) in operations.as_object().unwrap() {
+                if path == "/ws/fib" {
+                    let socket = warp::test::ws().path(path).handshake(routes.clone()).await;
+                    assert!(socket.is_ok(), "{} {} does not upgrade", method, path);
+                    assert!(operation["responses"]["101"].is_object());
+                    continue;
+                }
+
+                // Out-of-range inputs, unknown ids and empty bodies, so that
+                // no request proves.
+                let id = if path.starts_with("/jobs") {
+                    Uuid::nil().to_string()
+                } else {
+                    "1".to_string()
+                };
+                let uri = path.replace("{n}", "0").replace("{id}", &id);
+                let mut request = warp::test::request()
+                    .method(&method.to_uppercase())
+                    .path(&uri);
+                if operation["requestBody"]["content"]["application/json"].is_object() {
+                    request = request
+                        .header("content-type", "application/json")
+                        .body("{}");
+                }
+                let response = request.reply(&routes).await;
+
+                let status = response.status().as_str().to_string();
+                let body: serde_json::Value =
+                    serde_json::from_slice(response.body()).unwrap_or_default();
+                assert_ne!(
+                    body,
+                    serde_json::json!({ "error": "Not found", "kind": "not_found" }),
+                    "{} {} is documented but not served",
+                    method,
+                    path
+                );
+                assert!(
+                    operation["responses"][&status].is_object(),
+                    "{} {} does not document status {}",
+                    method,
+                    path,
+                    status
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_the_crate_version() {
+        let response = warp::test::request()
+            .path("/version")
+            .reply(&routes(settings()))
+            .await;
+        assert_eq!(response.status(), warp::http::StatusCode::OK);
+        let info: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+    }
+
+    #[tokio::test]
+    async fn only_allows_configured_origins() {
+        let routes = routes(Settings {
+            cors: cors_policy(Some("https://app.example, https://admin.example")),
+            ..settings()
+        });
+        let request = |origin: &str| {
+            warp::test::request()
+                .path("/health")
+                .header("origin", origin)
+        };
+
+        let allowed = request("https://admin.example").reply(&routes).await;
+        assert_eq!(allowed.status(), warp::http::StatusCode::OK);
+        assert_eq!(
+            allowed.headers()["access-control-allow-origin"],
+            "https://admin.example"
+        );
+
+        let forbidden = request("https://evil.example").reply(&routes).await;
+        assert_eq!(forbidden.status(), warp::http::StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn serves_health_checks_while_a_proof_runs() {
+        let routes = routes(settings());
+        let (started_tx, started_rx) = tokio::sync::oneshot::channel();
+        let (finish_tx, finish_rx) = std::sync::mpsc::channel::<()>();
+        let proof = tokio::spawn(offload(Duration::from_secs(60), move || {
+            started_tx.send(()).unwrap();
+            finish_rx.recv().unwrap();
+            "proven"
+        }));
+        started_rx.await.unwrap();
+
+        let health = warp::test::request().path("/health").reply(&routes);
+        let health = tokio::time::timeout(Duration::from_secs(1), health)
+            .await
+            .expect("/health waited for the proof");
+        assert_eq!(health.status(), warp::http::StatusCode::OK);
+
+        finish_tx.send(()).unwrap();
+        let proof = proof.await.unwrap().unwrap();
+        assert_eq!(proof.status(), warp::http::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn times_out_slow_proofs() {
+        let response = offload(Duration::from_millis(10), || {
+            std::thread::sleep(Duration::from_millis(200));
+            "late"
+        })
+        .await
+        .unwrap();
+        assert_eq!(response.status(), warp::http::StatusCode::GATEWAY_TIMEOUT);
+        let body = warp::hyper::body::to_bytes(response.into_body())
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["kind"], "timeout");
+    }
+
+    #[tokio::test]
+    async fn reports_out_of_range_inputs_as_structured_errors() {
+        let routes = routes(settings());
+        let selftest = warp::test::request()
+            .method("POST")
+            .path("/selftest/0")
+            .reply(&routes)
+            .await;
+        let job = warp::test::request()
+            .method("POST")
+            .path("/jobs")
+            .json(&serde_json::json!({ "n": 0 }))
+            .reply(&routes)
+            .await;
+
+        for response in [selftest, job] {
+            assert_eq!(response.status(), warp::http::StatusCode::BAD_REQUEST);
+            let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+            assert_eq!(
+                body,
+                serde_json::json!({
+                    "error": "Input out of range: expected 1 <= n <= 93, got 0",
+                    "kind": "input_out_of_range",
+                })
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn accepts_jobs_with_their_id() {
+        // Hold the job in the stub prover, so it stays pending and nothing
+        // reaches the zkVM.
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        let release_rx = std::sync::Mutex::new(release_rx);
+        let jobs = jobs::with_prover(
+            Duration::from_secs(60),
+            8,
+            Duration::from_secs(60),
+            move |_| {
+                let _ = release_rx.lock().unwrap().recv();
+                Err(Error::Prove("not proven in tests".to_string()))
+            },
+        );
+        let routes = routes(Settings { jobs, ..settings() });
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/jobs")
+            .json(&serde_json::json!({ "n": 4 }))
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), warp::http::StatusCode::ACCEPTED);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        let job_id = body["job_id"].as_str().unwrap();
+        assert!(Uuid::parse_str(job_id).is_ok());
+        assert_eq!(body.as_object().unwrap().len(), 1);
+
+        let status = warp::test::request()
+            .path(&format!("/jobs/{}", job_id))
+            .reply(&routes)
+            .await;
+        assert_eq!(status.status(), warp::http::StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(status.body()).unwrap();
+        assert_eq!(body, serde_json::json!({ "status": "pending" }));
+        drop(release_tx);
+    }
+
+    #[tokio::test]
+    async fn turns_traffic_away_until_ready() {
+        let settings = Settings {
+            ready: Arc::new(AtomicBool::new(false)),
+            ..settings()
+        };
+        let ready = settings.ready.clone();
+        let routes = routes(settings);
+        let probe = || warp::test::request().path("/health/ready").reply(&routes);
+
+        let starting = probe().await;
+        assert_eq!(
+            starting.status(),
+            warp::http::StatusCode::SERVICE_UNAVAILABLE
+        );
+        let body: serde_json::Value = serde_json::from_slice(starting.body()).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "error": "Server is starting up; retry shortly",
+                "kind": "not_ready",
+            })
+        );
+
+        ready.store(true, Ordering::Release);
+        assert_eq!(probe().await.status(), warp::http::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn echoes_the_client_request_id() {
+        let response = warp::test::request()
+            .path("/health")
+            .header("x-request-id", "my-trace-123")
+            .reply(&routes(settings()))
+            .await;
+        assert_eq!(response.headers()["x-request-id"], "my-trace-123");
+    }
+
+    #[tokio::test]
+    async fn rejects_oversized_bodies() {
+        let response = warp::test::request()
+            .method("POST")
+            .path("/verify")
+            .header("content-type", "application/json")
+            .body(vec![b' '; 2048])
+            .reply(&routes(settings()))
+            .await;
+        assert_eq!(response.status(), warp::http::StatusCode::PAYLOAD_TOO_LARGE);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "error": "Request body too large",
+                "kind": "payload_too_large",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_form_encoded_bodies() {
+        let response = warp::test::request()
+            .method("POST")
+            .path("/verify")
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body("proof=00")
+            .reply(&routes(settings()))
+            .await;
+        assert_eq!(
+            response.status(),
+            warp::http::StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "error": "Unsupported content type: send the body as application/json",
+                "kind": "unsupported_media_type",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_invalid_bodies_as_unprocessable() {
+        let routes = routes(settings());
+        for (body, error) in [
+            (
+                r#"{"n": "four"}"#,
+                "Invalid request body: invalid type: string \"four\", expected u64 at `n`",
+            ),
+            (
+                r#"{"mode": "wide"}"#,
+                "Invalid request body: missing field `n`",
+            ),
+        ] {
+            let response = warp::test::request()
+                .method("POST")
+                .path("/jobs")
+                .header("content-type", "application/json")
+                .body(body)
+                .reply(&routes)
+                .await;
+            assert_eq!(
+                response.status(),
+                warp::http::StatusCode::UNPROCESSABLE_ENTITY
+            );
+            let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+            assert_eq!(
+                body,
+                serde_json::json!({ "error": error, "kind": "invalid_body" })
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn enforces_the_reported_limits() {
+        let routes = routes(settings());
+        let response = warp::test::request().path("/limits").reply(&routes).await;
+        assert_eq!(response.status(), warp::http::StatusCode::OK);
+        let limits: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+
+        let max_body_bytes = limits["max_body_bytes"].as_u64().unwrap() as usize;
+        let oversized = warp::test::request()
+            .method("POST")
+            .path("/verify")
+            .header("content-type", "application/json")
+            .body(vec![b' '; max_body_bytes + 1])
+            .reply(&routes)
+            .await;
+        assert_eq!(
+            oversized.status(),
+            warp::http::StatusCode::PAYLOAD_TOO_LARGE
+        );
+
+        let max_fib_n = limits["max_fib_n"].as_u64().unwrap();
+        let out_of_range = warp::test::request()
+            .method("POST")
+            .path(&format!("/fib/{}", max_fib_n + 1))
+            .reply(&routes)
+            .await;
+        assert_eq!(out_of_range.status(), warp::http::StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = serde_json::from_slice(out_of_range.body()).unwrap();
+        assert_eq!(body["kind"], "input_out_of_range");
+    }
+
+    #[tokio::test]
+    async fn queues_proofs_beyond_the_slots_and_keeps_serving() {
+        let routes = routes(settings());
+
+        // Hold every proving slot until released.
+        let (started_tx, mut started_rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut releases = Vec::new();
+        let mut held = Vec::new();
+        for _ in 0..slots::max_concurrent_proofs() {
+            let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+            let started_tx = started_tx.clone();
+            releases.push(release_tx);
+            held.push(tokio::spawn(slots::in_slot(move || {
+                started_tx.send(()).unwrap();
+                let _ = release_rx.recv();
+                Ok(())
+            })));
+        }
+        for _ in 0..held.len() {
+            started_rx.recv().await.unwrap();
+        }
+
+        let (extra_tx, mut extra_rx) = tokio::sync::oneshot::channel();
+        let extra = tokio::spawn(slots::in_slot(move || {
+            extra_tx.send(()).unwrap();
+            Ok(())
+        }));
+        let early = tokio::time::timeout(Duration::from_millis(100), &mut extra_rx).await;
+        assert!(early.is_err(), "A proof started without a free slot");
+
+        let health = warp::test::request().path("/health").reply(&routes);
+        let health = tokio::time::timeout(Duration::from_secs(1), health)
+            .await
+            .expect("/health waited for a proving slot");
+        assert_eq!(health.status(), warp::http::StatusCode::OK);
+
+        drop(releases);
+        extra_rx.await.unwrap();
+        extra.await.unwrap().unwrap();
+        for proof in held {
+            proof.await.unwrap().unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn stops_serving_once_the_shutdown_signal_fires() {
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let signal = async {
+            shutdown_rx.await.ok();
+        };
+        let (_, server) = warp::serve(routes(settings()))
+            .bind_with_graceful_shutdown(([127, 0, 0, 1], 0), signal);
+        let server = tokio::spawn(server);
 
-    // Start the server on port 3030.
-    warp::serve(routes).run(([0, 0, 0, 0], 3030)).await;
+        shutdown_tx.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .expect("The server kept running after the shutdown signal")
+            .unwrap();
+    }
 }
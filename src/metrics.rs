@@ -0,0 +1,99 @@
+use prometheus::{
+    Encoder, Gauge, Histogram, HistogramOpts, IntCounterVec, Opts, Registry, TextEncoder,
+};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+// Upper bounds, in seconds, of the proving time histogram buckets.
+const PROVING_TIME_BUCKETS: &[f64] = &[0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0];
+
+/// Prometheus metrics describing the zkVM prover.
+pub struct Metrics {
+    registry: Registry,
+    proving_seconds: Histogram,
+    proofs: IntCounterVec,
+    last_proving_seconds: Gauge,
+}
+
+// Process-wide metrics, registered on first use.
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+/// Returns the process-wide metrics.
+pub fn metrics() -> &'static Metrics {
+    METRICS.get_or_init(Metrics::new)
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+        let proving_seconds = Histogram::with_opts(
+            HistogramOpts::new(
+                "fib_proving_duration_seconds",
+                "Time spent proving a Fibonacci receipt in the zkVM",
+            )
+            .buckets(PROVING_TIME_BUCKETS.to_vec()),
+        )
+        .unwrap();
+        let proofs = IntCounterVec::new(
+            Opts::new("fib_proofs_total", "Proofs attempted, by outcome"),
+            &["outcome"],
+        )
+        .unwrap();
+        let last_proving_seconds = Gauge::with_opts(Opts::new(
+            "fib_last_proving_duration_seconds",
+            "Duration of the most recent proof",
+        ))
+        .unwrap();
+        registry
+            .register(Box::new(proving_seconds.clone()))
+            .unwrap();
+        registry.register(Box::new(proofs.clone())).unwrap();
+        registry
+            .register(Box::new(last_proving_seconds.clone()))
+            .unwrap();
+        Metrics {
+            registry,
+            proving_seconds,
+            proofs,
+            last_proving_seconds,
+        }
+    }
+
+    /// Records the duration and outcome of a call to the prover.
+    pub fn observe_proof(&self, elapsed: Duration, success: bool) {
+        let seconds = elapsed.as_secs_f64();
+        self.proving_seconds.observe(seconds);
+        self.last_proving_seconds.set(seconds);
+        let outcome = if success { "success" } else { "failure" };
+        self.proofs.with_label_values(&[outcome]).inc();
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_an_observed_proof() {
+        let metrics = Metrics::new();
+        metrics.observe_proof(Duration::from_millis(1500), true);
+        let rendered = metrics.render();
+        for line in [
+            "fib_proving_duration_seconds_count 1",
+            "fib_proving_duration_seconds_bucket{le=\"2.5\"} 1",
+            "fib_proofs_total{outcome=\"success\"} 1",
+            "fib_last_proving_duration_seconds 1.5",
+        ] {
+            assert!(rendered.lines().any(|l| l == line), "{}", rendered);
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use crate::worker::{self, VerifyRequest, VerifyResult};
+use futures_util::{stream, Stream, StreamExt};
+use std::convert::Infallible;
+use warp::hyper::body::{Buf, Bytes};
+
+// Media type for newline-delimited JSON.
+pub const NDJSON: &str = "application/x-ndjson";
+
+// Splits a streamed request body into lines without buffering more than one
+// line at a time.
+struct Lines<S> {
+    body: S,
+    buffer: Vec<u8>,
+    max_line_bytes: usize,
+    done: bool,
+}
+
+// Define a line read from the body: its bytes, or a marker that it exceeded
+// the limit and was discarded.
+enum Line {
+    Complete(Vec<u8>),
+    Oversized,
+}
+
+impl<S, B> Lines<S>
+where
+    S: Stream<Item = Result<B, warp::Error>> + Unpin,
+    B: Buf,
+{
+    /// Returns the next line, without its newline, or `None` once the body is
+    /// exhausted. Ends early if the body fails or a line exceeds the limit.
+    async fn next_line(&mut self) -> Option<Line> {
+        loop {
+            let newline = self.buffer.iter().position(|&byte| byte == b'\n');
+            if newline.unwrap_or(self.buffer.len()) > self.max_line_bytes {
+                self.done = true;
+                self.buffer = Vec::new();
+                return Some(Line::Oversized);
+            }
+            if let Some(end) = newline {
+                let mut line: Vec<u8> = self.buffer.drain(..=end).collect();
+                line.pop();
+                return Some(Line::Complete(line));
+            }
+            if self.done {
+                return (!self.buffer.is_empty())
+                    .then(|| Line::Complete(std::mem::take(&mut self.buffer)));
+            }
+            match self.body.next().await {
+                Some(Ok(mut chunk)) => {
+                    while chunk.has_remaining() {
+                        let bytes = chunk.chunk();
+                        let len = bytes.len();
+                        self.buffer.extend_from_slice(bytes);
+                        chunk.advance(len);
+                    }
+                }
+                Some(Err(_)) | None => self.done = true,
+            }
+        }
+    }
+}
+
+/// Verifies a stream of newline-delimited `VerifyRequest` objects, yielding
+/// one `VerifyResult` line per non-empty input line as soon as it has been
+/// verified. Lines that are not valid requests are reported as invalid
+/// proofs. A line longer than `max_line_bytes` is reported as invalid and ends
+/// the stream.
+pub fn verify_stream<S, B>(
+    body: S,
+    max_line_bytes: usize,
+) -> impl Stream<Item = Result<Bytes, Infallible>>
+where
+    S: Stream<Item = Result<B, warp::Error>> + Unpin + Send + 'static,
+    B: Buf + Send,
+{
+    let lines = Lines {
+        body,
+        buffer: Vec::new(),
+        max_line_bytes,
+        done: false,
+    };
+    stream::unfold(lines, |mut lines| async move {
+        let line = loop {
+            match lines.next_line().await? {
+                Line::Complete(line) if line.iter().all(u8::is_ascii_whitespace) => continue,
+                line => break line,
+            }
+        };
+        let request = match line {
+            Line::Complete(line) => serde_json::from_slice::<VerifyRequest>(&line).ok(),
+            Line::Oversized => None,
+        };
+        let result = match request {
+            Some(request) => tokio::task::spawn_blocking(move || worker::verify(&request))
+                .await
+                .unwrap_or_else(|_| invalid()),
+            None => invalid(),
+        };
+        let mut output = serde_json::to_vec(&result).unwrap();
+        output.push(b'\n');
+        Some((Ok(Bytes::from(output)), lines))
+    })
+}
+
+/// Result reported for a line that could not be verified.
+fn invalid() -> VerifyResult {
+    VerifyResult {
+        is_valid: false,
+        op: None,
+        mode: None,
+        input: None,
+        result: None,
+        sequence: None,
+        nonce: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(
+        chunks: &[&'static str],
+        max_line_bytes: usize,
+    ) -> Lines<impl Stream<Item = Result<Bytes, warp::Error>> + Unpin> {
+        let chunks: Vec<Result<Bytes, warp::Error>> =
+            chunks.iter().map(|chunk| Ok(Bytes::from(*chunk))).collect();
+        Lines {
+            body: stream::iter(chunks),
+            buffer: Vec::new(),
+            max_line_bytes,
+            done: false,
+        }
+    }
+
+    async fn collect(
+        mut lines: Lines<impl Stream<Item = Result<Bytes, warp::Error>> + Unpin>,
+    ) -> Vec<Vec<u8>> {
+        let mut collected = Vec::new();
+        while let Some(line) = lines.next_line().await {
+            match line {
+                Line::Complete(line) => collected.push(line),
+                Line::Oversized => panic!("unexpected oversized line"),
+            }
+        }
+        collected
+    }
+
+    #[tokio::test]
+    async fn splits_lines_across_chunks() {
+        let collected = collect(lines(&["{\"a\"", ":1}\n{}", "\n\nlast"], 16)).await;
+        assert_eq!(
+            collected,
+            vec![
+                b"{\"a\":1}".to_vec(),
+                b"{}".to_vec(),
+                Vec::new(),
+                b"last".to_vec()
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn accepts_lines_at_the_limit() {
+        let collected = collect(lines(&["0123\n", "4567"], 4)).await;
+        assert_eq!(collected, vec![b"0123".to_vec(), b"4567".to_vec()]);
+    }
+
+    #[tokio::test]
+    async fn discards_oversized_lines() {
+        // A complete line over the limit, in a single chunk.
+        let mut complete = lines(&["0123456789\n{}\n"], 4);
+        assert!(matches!(complete.next_line().await, Some(Line::Oversized)));
+        assert!(complete.next_line().await.is_none());
+
+        // A line that grows over the limit without a newline.
+        let mut partial = lines(&["012", "345", "678"], 4);
+        assert!(matches!(partial.next_line().await, Some(Line::Oversized)));
+        assert!(partial.next_line().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn reports_oversized_lines_as_invalid() {
+        let body = stream::iter(vec![Ok::<_, warp::Error>(Bytes::from("0123456789\n"))]);
+        let output: Vec<_> = verify_stream(body, 4).collect().await;
+        assert_eq!(output.len(), 1);
+        let result: serde_json::Value =
+            serde_json::from_slice(output[0].as_ref().unwrap()).unwrap();
+        assert_eq!(result["is_valid"], false);
+    }
+
+    #[tokio::test]
+    async fn answers_every_line_in_order() {
+        // Undecodable proofs echo the requested mode, so the order of the
+        // results can be checked; malformed lines have none.
+        let mut body = String::new();
+        let mut expected = Vec::new();
+        for i in 0..300 {
+            match i % 4 {
+                0 => {
+                    body.push_str(r#"{"proof":"zz","image_id":"00","mode":"nth"}"#);
+                    expected.push(serde_json::json!("nth"));
+                }
+                1 => {
+                    body.push_str(r#"{"proof": "#);
+                    expected.push(serde_json::Value::Null);
+                }
+                2 => body.push_str("   "),
+                _ => {
+                    body.push_str(r#"{"proof":"00","image_id":"00","mode":"wide"}"#);
+                    expected.push(serde_json::json!("wide"));
+                }
+            }
+            body.push('\n');
+        }
+        let chunks: Vec<Result<Bytes, warp::Error>> = body
+            .as_bytes()
+            .chunks(7)
+            .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+            .collect();
+
+        let output: Vec<_> = verify_stream(stream::iter(chunks), 1024).collect().await;
+        assert_eq!(output.len(), expected.len());
+        for (line, mode) in output.iter().zip(&expected) {
+            let line = line.as_ref().unwrap();
+            assert_eq!(line.iter().filter(|&&byte| byte == b'\n').count(), 1);
+            let result: serde_json::Value = serde_json::from_slice(line).unwrap();
+            assert_eq!(result["is_valid"], false);
+            assert_eq!(&result["mode"], mode);
+        }
+    }
+}
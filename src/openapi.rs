@@ -0,0 +1,502 @@
+use serde_json::{json, Value};
+
+/// Builds the OpenAPI 3.0 document describing the HTTP API. This is
+/// maintained by hand and must be kept in sync with the routes in `main.rs`
+/// and the request and response types in `worker.rs`.
+pub fn spec() -> Value {
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "risc-v",
+            "description": "Proves and verifies Fibonacci and factorial computations with the RISC Zero zkVM.",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": {
+            "/health": {
+                "get": {
+                    "summary": "Health check",
+                    "responses": { "200": { "description": "The server is up" } },
+                },
+            },
+            "/health/live": {
+                "get": {
+                    "summary": "Liveness probe",
+                    "responses": { "200": { "description": "The process is serving requests" } },
+                },
+            },
+            "/health/ready": {
+                "get": {
+                    "summary": "Readiness probe",
+                    "parameters": [query("deep", json!({ "type": "boolean", "default": false }))],
+                    "responses": {
+                        "200": { "description": "The service can prove and verify" },
+                        "400": described_error("The query is invalid"),
+                        "500": error(),
+                        "503": described_error("The service is starting up or the prover is unavailable"),
+                        "504": error(),
+                    },
+                },
+            },
+            "/image-id": {
+                "get": {
+                    "summary": "Compare the configured and embedded guest image ids",
+                    "responses": {
+                        "200": ok("ImageIdInfo"),
+                        "500": error(),
+                    },
+                },
+            },
+            "/version": {
+                "get": {
+                    "summary": "Report the deployed build and prover",
+                    "responses": {
+                        "200": ok("VersionInfo"),
+                        "500": error(),
+                    },
+                },
+            },
+            "/limits": {
+                "get": {
+                    "summary": "Report the limits enforced by this server",
+                    "responses": { "200": ok("Limits") },
+                },
+            },
+            "/metrics": {
+                "get": {
+                    "summary": "Prover metrics in the Prometheus text format",
+                    "responses": {
+                        "200": {
+                            "description": "Prometheus metrics",
+                            "content": { "text/plain": { "schema": { "type": "string" } } },
+                        },
+                    },
+                },
+            },
+            "/openapi.json": {
+                "get": {
+                    "summary": "This document",
+                    "responses": { "200": { "description": "The OpenAPI document" } },
+                },
+            },
+            "/challenge": {
+                "get": {
+                    "summary": "Issue a single-use nonce to bind into a proof",
+                    "responses": { "200": ok("Challenge"), "503": error() },
+                },
+            },
+            "/fib/{n}": {
+                "post": {
+                    "summary": "Prove the Fibonacci computation for position n",
+                    "parameters": [
+                        path_n(),
+                        query("mode", schema_ref("Mode")),
+                        query("encoding", schema_ref("Encoding")),
+                        query("nonce", string()),
+                        header("Idempotency-Key"),
+                    ],
+                    "responses": {
+                        "200": {
+                            "description": "The result and its receipt",
+                            "content": {
+                                "application/json": { "schema": schema_ref("FibResult") },
+                                "application/cbor": { "schema": schema_ref("RawFibResult") },
+                            },
+                        },
+                        "400": error(),
+                        "422": error(),
+                        "500": error(),
+                        "503": error(),
+                        "504": error(),
+                    },
+                },
+            },
+            "/fib/{n}/raw": {
+                "post": {
+                    "summary": "Prove the Fibonacci computation and return the raw receipt bytes",
+                    "parameters": [path_n(), query("mode", schema_ref("Mode"))],
+                    "responses": {
+                        "200": {
+                            "description": "The bincode-serialized receipt",
+                            "content": { "application/octet-stream": { "schema": { "type": "string", "format": "binary" } } },
+                        },
+                        "400": error(),
+                        "500": error(),
+                        "503": error(),
+                        "504": error(),
+                    },
+                },
+            },
+            "/compute": {
+                "post": {
+                    "summary": "Prove a selectable operation",
+                    "requestBody": body("ComputeRequest"),
+                    "responses": {
+                        "200": ok("ComputeResult"),
+                        "400": error(),
+                        "411": described_error("The Content-Length header is missing"),
+                        "413": described_error("The request body is too large"),
+                        "415": described_error("The request body is not JSON"),
+                        "422": described_error("The request body is invalid"),
+                        "500": error(),
+                        "503": error(),
+                        "504": error(),
+                    },
+                },
+            },
+            "/selftest/{n}": {
+                "post": {
+                    "summary": "Prove and verify fib(n) in one round trip",
+                    "parameters": [path_n()],
+                    "responses": {
+                        "200": ok("SelfTestResult"),
+                        "400": error(),
+                        "500": error(),
+                        "503": error(),
+                        "504": error(),
+                    },
+                },
+            },
+            "/proofs": {
+                "get": {
+                    "summary": "List recorded proofs",
+                    "parameters": [
+                        query("circuit", json!({ "type": "string" })),
+                        query("since", json!({ "type": "integer", "format": "int64" })),
+                        query("limit", json!({ "type": "integer", "minimum": 0, "maximum": 1000, "default": 100 })),
+                        query("offset", json!({ "type": "integer", "minimum": 0, "default": 0 })),
+                    ],
+                    "responses": {
+                        "200": {
+                            "description": "Matching proof records, without their receipts",
+                            "content": {
+                                "application/json": {
+                                    "schema": { "type": "array", "items": schema_ref("ProofSummary") },
+                                },
+                            },
+                        },
+                        "400": described_error("The query is invalid"),
+                        "404": described_error("Proof history is disabled"),
+                        "500": error(),
+                    },
+                },
+            },
+            "/proofs/{id}": {
+                "get": {
+                    "summary": "Fetch a recorded proof",
+                    "parameters": [{
+                        "name": "id",
+                        "in": "path",
+                        "required": true,
+                        "schema": { "type": "integer", "format": "int64" },
+                    }],
+                    "responses": {
+                        "200": ok("ProofRecord"),
+                        "404": described_error("No proof with this id, or proof history is disabled"),
+                        "500": error(),
+                    },
+                },
+            },
+            "/jobs": {
+                "post": {
+                    "summary": "Submit a proving job to run in the background",
+                    "requestBody": body("JobRequest"),
+                    "responses": {
+                        "202": ok("JobCreated"),
+                        "400": error(),
+                        "411": described_error("The Content-Length header is missing"),
+                        "413": described_error("The request body is too large"),
+                        "415": described_error("The request body is not JSON"),
+                        "422": described_error("The request body is invalid"),
+                        "503": error(),
+                    },
+                },
+            },
+            "/jobs/{id}": {
+                "get": {
+                    "summary": "Poll a proving job",
+                    "parameters": [{
+                        "name": "id",
+                        "in": "path",
+                        "required": true,
+                        "schema": { "type": "string", "format": "uuid" },
+                    }],
+                    "responses": {
+                        "200": ok("JobState"),
+                        "404": described_error("No job with this id"),
+                    },
+                },
+            },
+            "/ws/fib": {
+                "get": {
+                    "summary": "WebSocket that streams proving progress and the result",
+                    "responses": {
+                        "101": { "description": "Switching to the WebSocket protocol" },
+                        "503": described_error("The server is starting up"),
+                    },
+                },
+            },
+            "/verify": {
+                "post": {
+                    "summary": "Verify a receipt against an image id",
+                    "requestBody": body("VerifyRequest"),
+                    "responses": {
+                        "200": ok("VerifyResult"),
+                        "411": described_error("The Content-Length header is missing"),
+                        "413": described_error("The request body is too large"),
+                        "415": described_error("The request body is not JSON"),
+                        "422": described_error("The request body is invalid"),
+                        "500": error(),
+                    },
+                },
+            },
+            "/verify/raw": {
+                "post": {
+                    "summary": "Verify raw receipt bytes against an image id",
+                    "parameters": [
+                        {
+                            "name": "X-Image-Id",
+                            "in": "header",
+                            "required": true,
+                            "schema": { "type": "string" },
+                        },
+                        query("mode", schema_ref("Mode")),
+                    ],
+                    "requestBody": {
+                        "required": true,
+                        "content": { "application/octet-stream": { "schema": { "type": "string", "format": "binary" } } },
+                    },
+                    "responses": {
+                        "200": ok("VerifyResult"),
+                        "400": described_error("The X-Image-Id header or the query is missing or invalid"),
+                        "411": described_error("The Content-Length header is missing"),
+                        "413": described_error("The request body is too large"),
+                        "500": error(),
+                    },
+                },
+            },
+            "/verify/stream": {
+                "post": {
+                    "summary": "Verify newline-delimited JSON verification requests",
+                    "requestBody": {
+                        "required": true,
+                        "content": { "application/x-ndjson": { "schema": schema_ref("VerifyRequest") } },
+                    },
+                    "responses": {
+                        "200": {
+                            "description": "One verification result per input line",
+                            "content": { "application/x-ndjson": { "schema": schema_ref("VerifyResult") } },
+                        },
+                    },
+                },
+            },
+        },
+        "components": { "schemas": schemas() },
+    })
+}
+
+/// Schemas of the request and response bodies.
+fn schemas() -> Value {
+    json!({
+        "Mode": { "type": "string", "enum": ["nth", "wide", "sequence"], "default": "nth" },
+        "Op": { "type": "string", "enum": ["fib", "factorial"], "default": "fib" },
+        "Encoding": { "type": "string", "enum": ["hex", "base64"], "default": "hex" },
+        "ErrorResponse": object(
+            &["error", "kind"],
+            json!({ "error": string(), "kind": string() }),
+        ),
+        "Challenge": object(
+            &["nonce", "expires_in_secs"],
+            json!({ "nonce": string(), "expires_in_secs": integer() }),
+        ),
+        "FibResult": object(
+            &["mode", "result", "journal_hex", "encoding", "proof", "generated_at", "proving_time_ms", "circuit", "prover_version"],
+            json!({
+                "mode": schema_ref("Mode"),
+                "result": integer(),
+                "sequence": { "type": "array", "items": integer() },
+                "nonce": string(),
+                "journal_hex": string(),
+                "encoding": schema_ref("Encoding"),
+                "proof": string(),
+                "generated_at": integer(),
+                "proving_time_ms": integer(),
+                "circuit": string(),
+                "prover_version": string(),
+            }),
+        ),
+        "RawFibResult": object(
+            &["mode", "result", "journal", "proof", "generated_at", "proving_time_ms", "circuit", "prover_version"],
+            json!({
+                "mode": schema_ref("Mode"),
+                "result": { "type": "integer", "description": "A CBOR positive bignum (tag 2) above u64::MAX" },
+                "sequence": { "type": "array", "items": integer() },
+                "nonce": string(),
+                "journal": { "type": "string", "format": "binary" },
+                "proof": { "type": "string", "format": "binary" },
+                "generated_at": integer(),
+                "proving_time_ms": integer(),
+                "circuit": string(),
+                "prover_version": string(),
+            }),
+        ),
+        "ComputeRequest": object(
+            &["op", "n"],
+            json!({ "op": schema_ref("Op"), "n": integer(), "encoding": schema_ref("Encoding") }),
+        ),
+        "ComputeResult": object(
+            &["op", "n", "result", "journal_hex", "encoding", "proof", "generated_at", "proving_time_ms", "prover_version"],
+            json!({
+                "op": schema_ref("Op"),
+                "n": integer(),
+                "result": integer(),
+                "journal_hex": string(),
+                "encoding": schema_ref("Encoding"),
+                "proof": string(),
+                "generated_at": integer(),
+                "proving_time_ms": integer(),
+                "prover_version": string(),
+            }),
+        ),
+        "SelfTestResult": object(
+            &["result", "proof", "is_valid"],
+            json!({ "result": integer(), "proof": string(), "is_valid": { "type": "boolean" } }),
+        ),
+        "ImageIdInfo": object(
+            &["configured", "computed", "matches"],
+            json!({ "configured": string(), "computed": string(), "matches": { "type": "boolean" } }),
+        ),
+        "VersionInfo": object(
+            &["version", "git_sha", "proof_system", "circuit", "image_id"],
+            json!({
+                "version": string(),
+                "git_sha": string(),
+                "proof_system": string(),
+                "circuit": string(),
+                "image_id": string(),
+            }),
+        ),
+        "Limits": object(
+            &["max_body_bytes", "prove_timeout_secs", "min_n", "max_fib_n", "max_fib_n_wide", "max_fib_n_sequence", "max_factorial_n", "max_concurrent_proofs"],
+            json!({
+                "max_body_bytes": integer(),
+                "prove_timeout_secs": integer(),
+                "min_n": integer(),
+                "max_fib_n": integer(),
+                "max_fib_n_wide": integer(),
+                "max_fib_n_sequence": integer(),
+                "max_factorial_n": integer(),
+                "max_concurrent_proofs": integer(),
+            }),
+        ),
+        "ProofRecord": object(
+            &["id", "circuit", "mode", "public_input", "proof", "created_at"],
+            json!({
+                "id": integer(),
+                "circuit": string(),
+                "mode": string(),
+                "public_input": string(),
+                "proof": string(),
+                "created_at": integer(),
+            }),
+        ),
+        "ProofSummary": object(
+            &["id", "circuit", "mode", "public_input", "created_at"],
+            json!({
+                "id": integer(),
+                "circuit": string(),
+                "mode": string(),
+                "public_input": string(),
+                "created_at": integer(),
+            }),
+        ),
+        "JobRequest": object(
+            &["n"],
+            json!({ "n": integer(), "mode": schema_ref("Mode"), "encoding": schema_ref("Encoding") }),
+        ),
+        "JobCreated": object(&["job_id"], json!({ "job_id": { "type": "string", "format": "uuid" } })),
+        "JobState": object(
+            &["status"],
+            json!({
+                "status": { "type": "string", "enum": ["pending", "done", "failed"] },
+                "result": schema_ref("FibResult"),
+                "error": string(),
+                "kind": string(),
+            }),
+        ),
+        "VerifyRequest": object(
+            &["proof", "image_id"],
+            json!({
+                "proof": string(),
+                "image_id": string(),
+                "encoding": schema_ref("Encoding"),
+                "mode": schema_ref("Mode"),
+                "nonce": string(),
+            }),
+        ),
+        "VerifyResult": object(
+            &["is_valid", "op", "mode", "input", "result"],
+            json!({
+                "is_valid": { "type": "boolean" },
+                "op": { "allOf": [schema_ref("Op")], "nullable": true },
+                "mode": { "allOf": [schema_ref("Mode")], "nullable": true },
+                "input": { "type": "integer", "nullable": true },
+                "result": { "type": "integer", "nullable": true },
+                "sequence": { "type": "array", "items": integer() },
+                "nonce": string(),
+            }),
+        ),
+    })
+}
+
+fn schema_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+fn object(required: &[&str], properties: Value) -> Value {
+    json!({ "type": "object", "required": required, "properties": properties })
+}
+
+fn string() -> Value {
+    json!({ "type": "string" })
+}
+
+fn integer() -> Value {
+    json!({ "type": "integer" })
+}
+
+fn query(name: &str, schema: Value) -> Value {
+    json!({ "name": name, "in": "query", "required": false, "schema": schema })
+}
+
+fn header(name: &str) -> Value {
+    json!({ "name": name, "in": "header", "required": false, "schema": string() })
+}
+
+fn path_n() -> Value {
+    json!({ "name": "n", "in": "path", "required": true, "schema": integer() })
+}
+
+fn body(schema: &str) -> Value {
+    json!({
+        "required": true,
+        "content": { "application/json": { "schema": schema_ref(schema) } },
+    })
+}
+
+fn ok(schema: &str) -> Value {
+    json!({
+        "description": "Success",
+        "content": { "application/json": { "schema": schema_ref(schema) } },
+    })
+}
+
+fn error() -> Value {
+    described_error("Error")
+}
+
+fn described_error(description: &str) -> Value {
+    json!({
+        "description": description,
+        "content": { "application/json": { "schema": schema_ref("ErrorResponse") } },
+    })
+}
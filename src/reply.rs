@@ -0,0 +1,301 @@
+use crate::error::ErrorResponse;
+use serde::{Serialize, Serializer};
+use serde_cbor::tags::Tagged;
+use serde_json::value::RawValue;
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use warp::http::header::HeaderValue;
+use warp::http::{header, StatusCode};
+use warp::reply::Response;
+use warp::Reply;
+
+// Media type for CBOR-encoded responses.
+pub const CBOR: &str = "application/cbor";
+
+// CBOR tag for a positive bignum (RFC 8949, section 3.4.3).
+const POSITIVE_BIGNUM: u64 = 2;
+
+// Media type for JSON responses.
+pub const JSON: &str = "application/json";
+
+// Media type for raw binary responses.
+pub const OCTET_STREAM: &str = "application/octet-stream";
+
+/// Returns whether the Accept header asks for a CBOR response.
+pub fn wants_cbor(accept: Option<&str>) -> bool {
+    accept.is_some_and(|accept| {
+        accept
+            .split(',')
+            .any(|media_type| media_type.trim().starts_with(CBOR))
+    })
+}
+
+/// Serializes a value as a CBOR response with the given status.
+pub fn cbor<T: Serialize>(value: &T, status: StatusCode) -> Response {
+    match serde_cbor::to_vec(value) {
+        Ok(body) => warp::reply::with_status(
+            warp::reply::with_header(body, header::CONTENT_TYPE, CBOR),
+            status,
+        )
+        .into_response(),
+        Err(e) => warp::reply::with_status(
+            warp::reply::json(&ErrorResponse::new(
+                "internal",
+                format!("Failed to serialize response: {}", e),
+            )),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+        .into_response(),
+    }
+}
+
+/// Serializes a `u128` as an integer. CBOR has no integers wider than 64 bits,
+/// so in binary formats larger values are written as a positive bignum: the
+/// minimal big-endian bytes under tag 2.
+pub fn wide_integer<S: Serializer>(value: &u128, serializer: S) -> Result<S::Ok, S::Error> {
+    match u64::try_from(*value) {
+        Ok(narrow) => serializer.serialize_u64(narrow),
+        Err(_) if !serializer.is_human_readable() => {
+            let bytes = value.to_be_bytes();
+            let start = (value.leading_zeros() / 8) as usize;
+            Tagged::new(
+                Some(POSITIVE_BIGNUM),
+                serde_bytes::Bytes::new(&bytes[start..]),
+            )
+            .serialize(serializer)
+        }
+        Err(_) => serializer.serialize_u128(*value),
+    }
+}
+
+/// Serializes an optional `u128` like [`wide_integer`].
+pub fn wide_integer_option<S: Serializer>(
+    value: &Option<u128>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    struct Wide(u128);
+
+    impl Serialize for Wide {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            wide_integer(&self.0, serializer)
+        }
+    }
+
+    match value {
+        Some(value) => serializer.serialize_some(&Wide(*value)),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Serializes a value as CBOR or JSON, depending on the Accept header.
+pub fn negotiated<T: Serialize>(accept: Option<&str>, value: &T, status: StatusCode) -> Response {
+    if wants_cbor(accept) {
+        cbor(value, status)
+    } else {
+        warp::reply::with_status(warp::reply::json(value), status).into_response()
+    }
+}
+
+/// Returns raw bytes as an `application/octet-stream` response, with extra
+/// headers carrying any metadata that does not fit in the body.
+pub fn octet_stream(body: Vec<u8>, headers: &[(&'static str, String)]) -> Response {
+    let mut response =
+        warp::reply::with_header(body, header::CONTENT_TYPE, OCTET_STREAM).into_response();
+    for (name, value) in headers {
+        if let Ok(value) = value.parse() {
+            response.headers_mut().insert(*name, value);
+        }
+    }
+    response
+}
+
+/// Rewrites a JSON response body in canonical form when `canonical` is set,
+/// so clients can hash it. Other responses are passed through unchanged.
+pub async fn canonical<T: Reply>(canonical: bool, reply: T) -> Result<Response, Infallible> {
+    let response = reply.into_response();
+    let is_json =
+        response.headers().get(header::CONTENT_TYPE) == Some(&HeaderValue::from_static(JSON));
+    if !canonical || !is_json {
+        return Ok(response);
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let rewritten = match warp::hyper::body::to_bytes(body).await {
+        Ok(body) => canonical_json(&body).map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    match rewritten {
+        Ok(body) => {
+            parts.headers.remove(header::CONTENT_LENGTH);
+            Ok(Response::from_parts(parts, body.into()))
+        }
+        Err(e) => Ok(warp::reply::with_status(
+            warp::reply::json(&ErrorResponse::new(
+                "internal",
+                format!("Failed to serialize response: {}", e),
+            )),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+        .into_response()),
+    }
+}
+
+/// Serializes a JSON document with object keys sorted and no whitespace, so
+/// equal values always produce identical bytes. Numbers are copied verbatim,
+/// keeping integers wider than 64 bits exact.
+pub fn canonical_json(json: &[u8]) -> Result<Vec<u8>, serde_json::Error> {
+    let value: &RawValue = serde_json::from_slice(json)?;
+    let mut out = Vec::with_capacity(json.len());
+    write_canonical(value, &mut out)?;
+    Ok(out)
+}
+
+fn write_canonical(value: &RawValue, out: &mut Vec<u8>) -> Result<(), serde_json::Error> {
+    match value.get().as_bytes().first() {
+        Some(b'{') => {
+            let fields: BTreeMap<String, &RawValue> = serde_json::from_str(value.get())?;
+            out.push(b'{');
+            for (i, (key, value)) in fields.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(b',');
+                }
+                serde_json::to_writer(&mut *out, &key)?;
+                out.push(b':');
+                write_canonical(value, out)?;
+            }
+            out.push(b'}');
+        }
+        Some(b'[') => {
+            let items: Vec<&RawValue> = serde_json::from_str(value.get())?;
+            out.push(b'[');
+            for (i, item) in items.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(b',');
+                }
+                write_canonical(item, out)?;
+            }
+            out.push(b']');
+        }
+        // Re-escape strings so equivalent escapes serialize the same way.
+        Some(b'"') => {
+            let text: String = serde_json::from_str(value.get())?;
+            serde_json::to_writer(&mut *out, &text)?;
+        }
+        _ => out.extend_from_slice(value.get().as_bytes()),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worker::{Mode, Op, RawFibResult, VerifyResult};
+
+    #[derive(Serialize)]
+    struct Wide {
+        #[serde(serialize_with = "wide_integer")]
+        result: u128,
+        #[serde(serialize_with = "wide_integer_option")]
+        optional: Option<u128>,
+    }
+
+    #[test]
+    fn encodes_wide_integers_as_cbor_bignums() {
+        let value = Wide {
+            result: 1 << 64,
+            optional: Some(3),
+        };
+        let body = serde_cbor::to_vec(&value).unwrap();
+        let bignum = [0xc2, 0x49, 1, 0, 0, 0, 0, 0, 0, 0, 0];
+        assert!(body.windows(bignum.len()).any(|window| window == bignum));
+
+        let decoded: serde_cbor::Value = serde_cbor::from_slice(&body).unwrap();
+        let serde_cbor::Value::Map(fields) = decoded else {
+            panic!("expected a map");
+        };
+        let optional = fields.get(&serde_cbor::Value::Text("optional".to_string()));
+        assert_eq!(optional, Some(&serde_cbor::Value::Integer(3)));
+    }
+
+    // Negotiates a CBOR response for the value and decodes its body.
+    async fn cbor_body<T: Serialize>(value: &T) -> BTreeMap<String, serde_cbor::Value> {
+        let response = negotiated(Some(CBOR), value, StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], CBOR);
+        let body = warp::hyper::body::to_bytes(response.into_body())
+            .await
+            .unwrap();
+        let serde_cbor::Value::Map(fields) = serde_cbor::from_slice(&body).unwrap() else {
+            panic!("expected a map");
+        };
+        fields
+            .into_iter()
+            .map(|(key, value)| match key {
+                serde_cbor::Value::Text(key) => (key, value),
+                key => panic!("unexpected key {:?}", key),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn negotiates_cbor_results() {
+        let proven = RawFibResult {
+            mode: Mode::Wide,
+            result: 1 << 64,
+            sequence: None,
+            nonce: None,
+            journal: vec![1, 2, 3],
+            proof: vec![4, 5, 6],
+            generated_at: 1_700_000_000_000,
+            proving_time_ms: 1234,
+            circuit: "fib",
+            prover_version: env!("CARGO_PKG_VERSION"),
+        };
+        let fields = cbor_body(&proven).await;
+        assert_eq!(fields["mode"], serde_cbor::Value::Text("wide".to_string()));
+        assert_eq!(
+            fields["result"],
+            serde_cbor::Value::Tag(
+                POSITIVE_BIGNUM,
+                Box::new(serde_cbor::Value::Bytes(vec![1, 0, 0, 0, 0, 0, 0, 0, 0]))
+            )
+        );
+        assert_eq!(fields["journal"], serde_cbor::Value::Bytes(vec![1, 2, 3]));
+        assert_eq!(fields["proof"], serde_cbor::Value::Bytes(vec![4, 5, 6]));
+
+        let verified = VerifyResult {
+            is_valid: true,
+            op: Some(Op::Fib),
+            mode: Some(Mode::Nth),
+            input: Some(10),
+            result: Some(55),
+            sequence: None,
+            nonce: None,
+        };
+        let fields = cbor_body(&verified).await;
+        assert_eq!(fields["is_valid"], serde_cbor::Value::Bool(true));
+        assert_eq!(fields["op"], serde_cbor::Value::Text("fib".to_string()));
+        assert_eq!(fields["input"], serde_cbor::Value::Integer(10));
+        assert_eq!(fields["result"], serde_cbor::Value::Integer(55));
+    }
+
+    #[test]
+    fn canonical_json_is_byte_stable() {
+        let first = br#"{ "b": [1, {"y": 2, "x": 1}], "a": 354224848179261915075 }"#;
+        let second = br#"{"a":354224848179261915075,"b":[1,{"x":1,"y":2}]}"#;
+        let expected = br#"{"a":354224848179261915075,"b":[1,{"x":1,"y":2}]}"#;
+        assert_eq!(canonical_json(first).unwrap(), expected);
+        assert_eq!(canonical_json(second).unwrap(), expected);
+        let again = canonical_json(expected).unwrap();
+        assert_eq!(canonical_json(&again).unwrap(), again);
+    }
+
+    #[test]
+    fn keeps_wide_integers_exact_in_json() {
+        let value = Wide {
+            result: 1 << 64,
+            optional: None,
+        };
+        let body = serde_json::to_string(&value).unwrap();
+        assert_eq!(body, r#"{"result":18446744073709551616,"optional":null}"#);
+    }
+}
@@ -0,0 +1,73 @@
+use crate::config::env_or;
+use crate::error::Error;
+use std::future::Future;
+use std::num::NonZeroUsize;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+// Default number of proofs allowed to run at once. Each proof holds its
+// execution trace in memory, so bursts beyond this wait for a free slot.
+const DEFAULT_MAX_CONCURRENT_PROOFS: &str = "2";
+
+static MAX_CONCURRENT_PROOFS: OnceLock<usize> = OnceLock::new();
+static PROOF_SLOTS: OnceLock<Semaphore> = OnceLock::new();
+
+/// Reads the cap on concurrent proofs from the MAX_CONCURRENT_PROOFS
+/// environment variable. Panics if the value is not a positive integer, so
+/// call it at startup.
+pub fn max_concurrent_proofs() -> usize {
+    *MAX_CONCURRENT_PROOFS.get_or_init(|| {
+        env_or::<NonZeroUsize>("MAX_CONCURRENT_PROOFS", DEFAULT_MAX_CONCURRENT_PROOFS).get()
+    })
+}
+
+/// Waits for a free proving slot. The slot is released when the returned
+/// permit is dropped, so move it into the blocking task that proves.
+pub async fn acquire() -> SemaphorePermit<'static> {
+    PROOF_SLOTS
+        .get_or_init(|| Semaphore::new(max_concurrent_proofs()))
+        .acquire()
+        .await
+        .expect("The proof slot semaphore is never closed")
+}
+
+/// Runs a blocking handler, such as a proof, on the blocking thread pool once
+/// a proving slot is free, holding the slot until the handler returns.
+pub async fn in_slot<F, T>(handler: F) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error> + Send + 'static,
+    T: Send + 'static,
+{
+    let permit = acquire().await;
+    tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        handler()
+    })
+    .await
+    .unwrap_or_else(|e| Err(Error::TaskFailed(e.to_string())))
+}
+
+/// Fails with [`Error::Timeout`] if `task` does not finish within `timeout`.
+/// A blocking thread started by the task cannot be interrupted, so a
+/// timed-out proof is left to finish in the background, holding its slot, and
+/// its result is discarded.
+pub async fn within<T>(
+    timeout: Duration,
+    task: impl Future<Output = Result<T, Error>>,
+) -> Result<T, Error> {
+    tokio::time::timeout(timeout, task)
+        .await
+        .unwrap_or_else(|_| Err(Error::Timeout(timeout.as_secs())))
+}
+
+/// Runs a blocking handler like [`in_slot`], failing with [`Error::Timeout`]
+/// if it does not finish within `timeout`, including time spent waiting for a
+/// slot.
+pub async fn run<F, T>(timeout: Duration, handler: F) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error> + Send + 'static,
+    T: Send + 'static,
+{
+    within(timeout, in_slot(handler)).await
+}
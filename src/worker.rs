@@ -1,60 +1,1136 @@
-use risc0_zkvm::{default_prover, ExecutorEnv};
-use serde::Serialize;
+use crate::challenge::{self, challenges, Nonce};
+use crate::config::env_or;
+use crate::error::Error;
+use crate::history::history;
+use crate::metrics::metrics;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use log::{debug, error, info, warn};
+use lru::LruCache;
+use risc0_zkvm::serde::Deserializer;
+use risc0_zkvm::{compute_image_id, default_prover, ExecutorEnv, Journal, Prover, Receipt};
+use serde::{Deserialize, Serialize};
 use std::env;
+use std::io;
+use std::num::NonZeroUsize;
+use std::panic;
+use std::rc::Rc;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+// Define the encodings supported for serialized receipts.
+#[derive(Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    #[default]
+    Hex,
+    Base64,
+}
+
+impl Encoding {
+    /// Encodes bytes as a string in this encoding.
+    pub fn encode(&self, bytes: &[u8]) -> String {
+        match self {
+            Encoding::Hex => hex::encode(bytes),
+            Encoding::Base64 => BASE64.encode(bytes),
+        }
+    }
+
+    /// Decodes a string in this encoding back into bytes.
+    pub fn decode(&self, data: &str) -> Result<Vec<u8>, Error> {
+        match self {
+            Encoding::Hex => hex::decode(data).map_err(Error::ProofHex),
+            Encoding::Base64 => BASE64.decode(data).map_err(Error::ProofBase64),
+        }
+    }
+}
+
+// Define the computation modes supported by the guest.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// fib(n) computed and committed as a u64.
+    #[default]
+    Nth,
+    /// fib(n) computed and committed as a u128, for larger positions.
+    Wide,
+    /// The whole sequence fib(1..=n) committed as a list of u64.
+    Sequence,
+}
+
+impl Mode {
+    /// Selector written into the executor environment for the guest.
+    fn selector(&self) -> u32 {
+        match self {
+            Mode::Nth => 0,
+            Mode::Wide => 1,
+            Mode::Sequence => 2,
+        }
+    }
+
+    /// Mode identified by a selector committed to the journal.
+    fn from_selector(selector: u32) -> Option<Self> {
+        match selector {
+            0 => Some(Mode::Nth),
+            1 => Some(Mode::Wide),
+            2 => Some(Mode::Sequence),
+            _ => None,
+        }
+    }
+
+    /// Name of the mode as used in requests and responses.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Mode::Nth => "nth",
+            Mode::Wide => "wide",
+            Mode::Sequence => "sequence",
+        }
+    }
+
+    /// Largest Fibonacci position whose value fits in this mode's output.
+    pub fn max_n(&self) -> u64 {
+        match self {
+            Mode::Nth | Mode::Sequence => MAX_FIB_N,
+            Mode::Wide => MAX_FIB_N_WIDE,
+        }
+    }
+}
+
+// Define the operations the guest can prove.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Op {
+    /// fib(n), as in [`Mode::Nth`].
+    #[default]
+    Fib,
+    /// n! computed and committed as a u128.
+    Factorial,
+}
+
+impl Op {
+    /// Selector written into the executor environment for the guest.
+    fn selector(&self) -> u32 {
+        match self {
+            Op::Fib => 0,
+            Op::Factorial => 1,
+        }
+    }
+
+    /// Name of the operation as used in requests and responses.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Op::Fib => "fib",
+            Op::Factorial => "factorial",
+        }
+    }
+
+    /// Operation identified by a selector committed to the journal.
+    fn from_selector(selector: u32) -> Option<Self> {
+        match selector {
+            0 => Some(Op::Fib),
+            1 => Some(Op::Factorial),
+            _ => None,
+        }
+    }
+
+    /// Largest input whose result fits in this operation's output.
+    pub fn max_n(&self) -> u64 {
+        match self {
+            Op::Fib => MAX_FIB_N,
+            Op::Factorial => MAX_FACTORIAL_N,
+        }
+    }
+}
+
+// Define the values committed to the journal by the guest.
+struct Committed {
+    op: Op,
+    mode: Mode,
+    input: u64,
+    nonce: Option<Nonce>,
+    result: u128,
+    sequence: Option<Vec<u64>>,
+}
+
+impl Committed {
+    /// Decodes the guest's journal. The guest commits the operation, the mode,
+    /// the input and the challenge nonce ahead of the result, so the result is
+    /// decoded according to what the receipt proves rather than what a client
+    /// claims it proves. Journals with words left over after the result are
+    /// rejected.
+    fn decode(journal: &Journal) -> Result<Self, Error> {
+        // A guest that panicked before committing leaves the journal empty.
+        if journal.bytes.is_empty() {
+            return Err(Error::EmptyJournal);
+        }
+        if !journal.bytes.len().is_multiple_of(4) {
+            return Err(Error::Decode(format!(
+                "journal length {} is not a whole number of words",
+                journal.bytes.len()
+            )));
+        }
+        let words: Vec<u32> = journal
+            .bytes
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect();
+        let mut remaining = words.as_slice();
+        let mut deserializer = Deserializer::new(&mut remaining);
+        let decode_error = |e: risc0_zkvm::serde::Error| Error::Decode(e.to_string());
+
+        let (op, mode, input, nonce) =
+            <(u32, u32, u64, Option<Nonce>)>::deserialize(&mut deserializer)
+                .map_err(decode_error)?;
+        let op = Op::from_selector(op)
+            .ok_or_else(|| Error::Decode(format!("unknown operation {}", op)))?;
+        let mode = Mode::from_selector(mode)
+            .ok_or_else(|| Error::Decode(format!("unknown mode {}", mode)))?;
+        let (result, sequence) = match (op, mode) {
+            (Op::Fib, Mode::Nth) => {
+                let result = u64::deserialize(&mut deserializer).map_err(decode_error)?;
+                (result as u128, None)
+            }
+            (Op::Fib, Mode::Wide) | (Op::Factorial, Mode::Nth) => {
+                let result = u128::deserialize(&mut deserializer).map_err(decode_error)?;
+                (result, None)
+            }
+            (Op::Fib, Mode::Sequence) => {
+                let sequence = Vec::<u64>::deserialize(&mut deserializer).map_err(decode_error)?;
+                let result = sequence.last().copied().unwrap_or_default() as u128;
+                (result, Some(sequence))
+            }
+            (Op::Factorial, _) => {
+                return Err(Error::Decode(format!(
+                    "unexpected mode {} for factorial",
+                    mode.as_str()
+                )))
+            }
+        };
+        if !remaining.is_empty() {
+            return Err(Error::Decode(format!(
+                "{} trailing words in journal",
+                remaining.len()
+            )));
+        }
+
+        Ok(Committed {
+            op,
+            mode,
+            input,
+            nonce,
+            result,
+            sequence,
+        })
+    }
+
+    /// Checks that the journal holds the operation, mode and input that were
+    /// requested from the guest.
+    fn expect(&self, op: Op, mode: Mode, input: u64) -> Result<(), Error> {
+        if self.op != op || self.mode != mode {
+            return Err(Error::Decode(format!(
+                "journal commits {} in {} mode, expected {} in {} mode",
+                self.op.as_str(),
+                self.mode.as_str(),
+                op.as_str(),
+                mode.as_str()
+            )));
+        }
+        if self.input != input {
+            return Err(Error::JournalMismatch {
+                expected: input,
+                got: self.input,
+            });
+        }
+        Ok(())
+    }
+}
 
 // Define the result structure to hold the result and proof.
-#[derive(Serialize)]
+#[derive(Clone, Serialize)]
 pub struct FibResult {
-    pub result: u64,
+    pub mode: Mode,
+    pub result: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<Vec<u64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    pub journal_hex: String,
+    pub encoding: Encoding,
+    pub proof: String,
+    pub generated_at: u128,
+    pub proving_time_ms: u128,
+    pub circuit: &'static str,
+    pub prover_version: &'static str,
+}
+
+// Define the request structure for verifying a receipt.
+#[derive(Deserialize)]
+pub struct VerifyRequest {
+    pub proof: String,
+    pub image_id: String,
+    #[serde(default)]
+    pub encoding: Encoding,
+    /// If set, only Fibonacci receipts proven in this mode are accepted. The
+    /// journal is always decoded according to the mode it commits.
+    #[serde(default)]
+    pub mode: Option<Mode>,
+    /// If set, the receipt must commit this challenge nonce.
+    #[serde(default)]
+    pub nonce: Option<String>,
+}
+
+// Define the request structure for proving a selectable operation.
+#[derive(Deserialize)]
+pub struct ComputeRequest {
+    pub op: Op,
+    pub n: u64,
+    #[serde(default)]
+    pub encoding: Encoding,
+}
+
+// Define the result structure for a proven operation.
+#[derive(Serialize)]
+pub struct ComputeResult {
+    pub op: Op,
+    pub n: u64,
+    pub result: u128,
+    pub journal_hex: String,
+    pub encoding: Encoding,
+    pub proof: String,
+    pub generated_at: u128,
+    pub proving_time_ms: u128,
+    pub prover_version: &'static str,
+}
+
+// Define the result structure for a combined prove-and-verify self-test.
+#[derive(Serialize)]
+pub struct SelfTestResult {
+    pub result: u128,
     pub proof: String,
+    pub is_valid: bool,
+}
+
+// Define the result structure comparing the configured and embedded image ids.
+#[derive(Serialize)]
+pub struct ImageIdInfo {
+    pub configured: String,
+    pub computed: String,
+    pub matches: bool,
+}
+
+// Define the result structure identifying the deployed build and prover.
+#[derive(Serialize)]
+pub struct VersionInfo {
+    pub version: &'static str,
+    pub git_sha: &'static str,
+    pub proof_system: &'static str,
+    pub circuit: &'static str,
+    pub image_id: String,
+}
+
+// Define the result structure carrying the raw journal and receipt bytes, for
+// binary response formats that do not need a text encoding.
+#[derive(Clone, Serialize)]
+pub struct RawFibResult {
+    pub mode: Mode,
+    #[serde(serialize_with = "crate::reply::wide_integer")]
+    pub result: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<Vec<u64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    #[serde(with = "serde_bytes")]
+    pub journal: Vec<u8>,
+    #[serde(with = "serde_bytes")]
+    pub proof: Vec<u8>,
+    pub generated_at: u128,
+    pub proving_time_ms: u128,
+    pub circuit: &'static str,
+    pub prover_version: &'static str,
+}
+
+impl RawFibResult {
+    /// Converts the raw bytes into the text-encoded result returned as JSON.
+    pub fn encode(self, encoding: Encoding) -> FibResult {
+        FibResult {
+            mode: self.mode,
+            result: self.result,
+            sequence: self.sequence,
+            nonce: self.nonce,
+            // Encode the committed journal so clients can read it without the receipt.
+            journal_hex: hex::encode(&self.journal),
+            encoding,
+            proof: encoding.encode(&self.proof),
+            generated_at: self.generated_at,
+            proving_time_ms: self.proving_time_ms,
+            circuit: self.circuit,
+            prover_version: self.prover_version,
+        }
+    }
+}
+
+// Define the result structure for a receipt verification.
+#[derive(Serialize)]
+pub struct VerifyResult {
+    pub is_valid: bool,
+    pub op: Option<Op>,
+    pub mode: Option<Mode>,
+    pub input: Option<u64>,
+    #[serde(serialize_with = "crate::reply::wide_integer_option")]
+    pub result: Option<u128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<Vec<u64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
 }
 
 // Embed the guest ELF.
 const GUEST_ELF: &[u8] = include_bytes!("GUEST.elf");
 
+// Bounds on the Fibonacci position accepted by the guest. fib(93) is the
+// largest Fibonacci number that fits in a u64, and fib(186) in a u128.
+pub const MIN_FIB_N: u64 = 1;
+pub const MAX_FIB_N: u64 = 93;
+pub const MAX_FIB_N_WIDE: u64 = 186;
+
+// Largest input whose factorial fits in a u128.
+pub const MAX_FACTORIAL_N: u64 = 34;
+
+// Name of the computation proven by the guest, reported in proof metadata.
+const CIRCUIT: &str = "fib";
+
 // Default IMAGE_ID if not provided via an environment variable.
-const DEFAULT_IMAGE_ID: &str = "7fbf2a9c7f59f4695fd21c52ed4836fe08558e91704d7d0020ce5bf71bc069bf";
+const DEFAULT_IMAGE_ID: &str = "4c2b049124a792ebb4e9cff145c2dbba3219c329fc1407c8dca2b202218900ba";
+
+// Default number of memoized Fibonacci results if not provided via an
+// environment variable. A size of 0 disables the cache.
+const DEFAULT_FIB_CACHE_SIZE: &str = "128";
+
+// Memoized results keyed by Fibonacci position and mode. Each receipt commits
+// to the exact input it was proven for, so serving it again is sound.
+type FibCache = Mutex<LruCache<(u64, Mode), RawFibResult>>;
+
+static FIB_CACHE_SIZE: OnceLock<usize> = OnceLock::new();
+static FIB_CACHE: OnceLock<Option<FibCache>> = OnceLock::new();
+
+/// Reads the number of memoized results from the FIB_CACHE_SIZE environment
+/// variable. Panics if the value is not a non-negative integer, so call it at
+/// startup.
+pub fn fib_cache_size() -> usize {
+    *FIB_CACHE_SIZE.get_or_init(|| env_or("FIB_CACHE_SIZE", DEFAULT_FIB_CACHE_SIZE))
+}
+
+/// Returns the process-wide result cache sized by [`fib_cache_size`], or
+/// `None` if caching is disabled.
+fn fib_cache() -> Option<&'static FibCache> {
+    FIB_CACHE
+        .get_or_init(|| {
+            NonZeroUsize::new(fib_cache_size()).map(|size| Mutex::new(LruCache::new(size)))
+        })
+        .as_ref()
+}
 
 /// Retrieves the IMAGE_ID from an environment variable at runtime,
 /// falling back to the default value if necessary. The hex string is then
 /// decoded into a 32-byte array.
-fn get_image_id() -> [u8; 32] {
+fn get_image_id() -> Result<[u8; 32], Error> {
     let image_id_str = env::var("IMAGE_ID").unwrap_or_else(|_| DEFAULT_IMAGE_ID.to_string());
-    let decoded = hex::decode(image_id_str).expect("Invalid hex string for IMAGE_ID");
+    parse_image_id(&image_id_str)
+}
+
+/// Decodes a hex-encoded image id into a 32-byte array.
+fn parse_image_id(image_id_str: &str) -> Result<[u8; 32], Error> {
+    let decoded = hex::decode(image_id_str).map_err(Error::ImageIdHex)?;
+    if decoded.len() != 32 {
+        return Err(Error::ImageIdLength(decoded.len()));
+    }
     let mut image_id = [0u8; 32];
-    image_id.copy_from_slice(&decoded[..32]);
-    image_id
+    image_id.copy_from_slice(&decoded);
+    Ok(image_id)
 }
 
-pub fn phi(a: u64) -> Result<FibResult, String> {
+/// Computes the image id of the embedded guest ELF and compares it with the
+/// configured IMAGE_ID, so a stale id is caught before any proof is attempted.
+pub fn image_id_info() -> Result<ImageIdInfo, Error> {
+    let configured = get_image_id()?;
+    let computed = compute_image_id(GUEST_ELF).map_err(|e| Error::ImageIdCompute(e.to_string()))?;
+    Ok(ImageIdInfo {
+        configured: hex::encode(configured),
+        computed: hex::encode(computed.as_bytes()),
+        matches: computed.as_bytes() == configured.as_slice(),
+    })
+}
+
+/// Reports the crate version, the git commit it was built from and the
+/// configured image id receipts are verified against.
+pub fn version_info() -> Result<VersionInfo, Error> {
+    Ok(VersionInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_sha: option_env!("GIT_SHA").unwrap_or("unknown"),
+        proof_system: "risc0-zkvm",
+        circuit: CIRCUIT,
+        image_id: hex::encode(get_image_id()?),
+    })
+}
+
+/// Checks that the Fibonacci position is within the range the guest can
+/// compute, so out-of-range inputs are rejected before paying for a proof.
+pub fn validate_input(a: u64, mode: Mode) -> Result<(), Error> {
+    if !(MIN_FIB_N..=mode.max_n()).contains(&a) {
+        return Err(Error::InputOutOfRange {
+            min: MIN_FIB_N,
+            max: mode.max_n(),
+            got: a,
+        });
+    }
+    Ok(())
+}
+
+/// Checks that the service is able to prove and verify requests. The cheap
+/// check validates the configured IMAGE_ID; the deep check additionally runs a
+/// full proof and verification round trip for the smallest input. The deep
+/// check bypasses the cache and the history, so every probe reaches the
+/// prover.
+pub fn check_ready(deep: bool) -> Result<(), Error> {
+    get_image_id()?;
+    if deep {
+        prove(MIN_FIB_N, Mode::default(), None)?;
+    }
+    Ok(())
+}
+
+pub fn phi(a: u64, mode: Mode, encoding: Encoding) -> Result<FibResult, Error> {
+    Ok(phi_raw(a, mode)?.encode(encoding))
+}
+
+/// Proves fib(a) like [`phi`], returning the journal and receipt as raw bytes.
+pub fn phi_raw(a: u64, mode: Mode) -> Result<RawFibResult, Error> {
+    // Reject inputs the guest would panic on.
+    validate_input(a, mode)?;
+
+    prove_cached(a, mode)
+}
+
+/// Proves fib(a) like [`phi_raw`], binding a challenge nonce from
+/// [`challenge::Challenges::issue`] into the journal. The receipt is then
+/// accepted by [`verify`] only once, while the challenge is outstanding, so
+/// it is never cached.
+pub fn phi_raw_challenged(a: u64, mode: Mode, nonce: &str) -> Result<RawFibResult, Error> {
+    validate_input(a, mode)?;
+    let nonce = challenge::parse_nonce(nonce)?;
+    // Fail before proving if the receipt could never be accepted.
+    challenges().check(&nonce)?;
+
+    let proven = prove(a, mode, Some(nonce))?;
+    record_proof(Op::Fib, a, mode, &proven.proof);
+    Ok(proven)
+}
+
+/// Records a generated receipt in the history under the name of the
+/// operation it proves, if enabled.
+fn record_proof(op: Op, n: u64, mode: Mode, receipt: &[u8]) {
+    if let Some(history) = history() {
+        let proof = hex::encode(receipt);
+        if let Err(e) = history.record_proof(op.as_str(), mode.as_str(), &n.to_string(), &proof) {
+            warn!("Failed to record proof in history: {}", e);
+        }
+    }
+}
+
+/// Serves repeated inputs from the cache instead of proving again.
+fn prove_cached(a: u64, mode: Mode) -> Result<RawFibResult, Error> {
+    if let Some(cache) = fib_cache() {
+        if let Some(proven) = cache.lock().unwrap().get(&(a, mode)) {
+            info!("Fibonacci cache hit for n={}", a);
+            return Ok(proven.clone());
+        }
+    }
+
+    let proven = prove(a, mode, None)?;
+    record_proof(Op::Fib, a, mode, &proven.proof);
+    if let Some(cache) = fib_cache() {
+        cache.lock().unwrap().put((a, mode), proven.clone());
+    }
+    Ok(proven)
+}
+
+/// Selects the prover backend like `default_prover`, which panics when the
+/// backend it picks (from RISC0_PROVER, BONSAI_API_URL and BONSAI_API_KEY) is
+/// not available. That case is reported as [`Error::ProverUnavailable`].
+fn select_prover() -> Result<Rc<dyn Prover>, Error> {
+    let explicit = env::var("RISC0_PROVER").unwrap_or_default();
+    let problem = match explicit.to_lowercase().as_str() {
+        "" | "ipc" => None,
+        "local" => Some(
+            "RISC0_PROVER=local is not supported: this build does not include the in-process \
+             prover"
+                .to_string(),
+        ),
+        "bonsai" if env::var("BONSAI_API_URL").is_err() || env::var("BONSAI_API_KEY").is_err() => {
+            Some("RISC0_PROVER=bonsai requires BONSAI_API_URL and BONSAI_API_KEY".to_string())
+        }
+        "bonsai" => None,
+        other => Some(format!("unsupported RISC0_PROVER '{}'", other)),
+    };
+    let selected = match problem {
+        Some(problem) => Err(problem),
+        None => panic::catch_unwind(default_prover).map_err(|payload| {
+            payload
+                .downcast_ref::<String>()
+                .cloned()
+                .or_else(|| payload.downcast_ref::<&str>().map(|s| s.to_string()))
+                .unwrap_or_else(|| "prover selection panicked".to_string())
+        }),
+    };
+    selected.map_err(prover_unavailable)
+}
+
+/// Logs which variables select a prover backend and reports the problem as
+/// [`Error::ProverUnavailable`].
+fn prover_unavailable(problem: String) -> Error {
+    error!(
+        "Prover backend unavailable: {}. Set RISC0_PROVER=ipc with r0vm on PATH (or \
+         RISC0_SERVER_PATH), or RISC0_PROVER=bonsai with BONSAI_API_URL and BONSAI_API_KEY",
+        problem
+    );
+    Error::ProverUnavailable(problem)
+}
+
+/// Maps a failed proof to an error. The ipc backend only finds out that r0vm
+/// is missing when it launches it, which fails with a not-found IO error; that
+/// is reported as [`Error::ProverUnavailable`] rather than a failed proof.
+fn prove_error(e: &(dyn std::error::Error + 'static)) -> Error {
+    let r0vm_missing = std::iter::successors(Some(e), |e| e.source()).any(|cause| {
+        cause
+            .downcast_ref::<io::Error>()
+            .is_some_and(|e| e.kind() == io::ErrorKind::NotFound)
+    });
+    if r0vm_missing {
+        prover_unavailable(format!("r0vm could not be launched: {}", e))
+    } else {
+        Error::Prove(e.to_string())
+    }
+}
+
+/// Runs the guest on the given inputs in the zkVM, verifies the resulting
+/// receipt and returns it with its generation time and proving time in ms.
+fn run_guest(
+    a: u64,
+    mode: Mode,
+    op: Op,
+    nonce: Option<Nonce>,
+) -> Result<(Receipt, u128, u128), Error> {
     // Build the executor environment and write the inputs.
     let env = ExecutorEnv::builder()
         .write(&a)
-        .map_err(|e| format!("Failed to write input a: {}", e))?
+        .map_err(|e| Error::WriteInput(e.to_string()))?
+        .write(&mode.selector())
+        .map_err(|e| Error::WriteInput(e.to_string()))?
+        .write(&op.selector())
+        .map_err(|e| Error::WriteInput(e.to_string()))?
+        .write(&nonce)
+        .map_err(|e| Error::WriteInput(e.to_string()))?
         .build()
-        .map_err(|e| format!("Failed to build environment: {}", e))?;
+        .map_err(|e| Error::BuildEnv(e.to_string()))?;
 
     // Create the prover instance.
-    let prover = default_prover();
-    let receipt = prover
-        .prove(env, GUEST_ELF)
-        .map_err(|e| format!("Failed to execute guest: {}", e))?
-        .receipt;
-
-    // Decode the guest's output (the result).
-    let result: u64 = receipt
-        .journal.decode()
-        .map_err(|e| format!("Failed to decode output: {}", e))?;
+    let prover = select_prover()?;
+    let generated_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let started = Instant::now();
+    let outcome = prover.prove(env, GUEST_ELF);
+    let elapsed = started.elapsed();
+    metrics().observe_proof(elapsed, outcome.is_ok());
+    let receipt = outcome.map_err(|e| prove_error(&*e))?.receipt;
 
     // Verify the receipt using the IMAGE_ID obtained from the environment.
-    let image_id = get_image_id();
+    let image_id = get_image_id()?;
     receipt.verify(image_id)
-        .map_err(|e| format!("Receipt verification failed: {}", e))?;
+        .map_err(|e| Error::Verification(e.to_string()))?;
+
+    Ok((receipt, generated_at, elapsed.as_millis()))
+}
+
+/// Proves fib(a) in the zkVM, optionally bound to a challenge nonce, and
+/// verifies the resulting receipt.
+fn prove(a: u64, mode: Mode, nonce: Option<Nonce>) -> Result<RawFibResult, Error> {
+    debug!("Proving fib({}) in {} mode", a, mode.as_str());
+
+    let (receipt, generated_at, proving_time_ms) = run_guest(a, mode, Op::Fib, nonce)?;
+
+    // Decode the guest's output (the operation, mode, input and result).
+    let committed = Committed::decode(&receipt.journal)?;
+    committed.expect(Op::Fib, mode, a)?;
+
+    // Serialize the receipt into bytes.
+    let receipt_bytes = bincode::serialize(&receipt).map_err(Error::Serialize)?;
+
+    Ok(RawFibResult {
+        mode,
+        result: committed.result,
+        sequence: committed.sequence,
+        nonce: committed.nonce.map(hex::encode),
+        journal: receipt.journal.bytes,
+        proof: receipt_bytes,
+        generated_at,
+        proving_time_ms,
+        circuit: CIRCUIT,
+        prover_version: env!("CARGO_PKG_VERSION"),
+    })
+}
+
+/// Checks that the input is within the range the guest can compute for the
+/// requested operation.
+pub fn validate_compute(request: &ComputeRequest) -> Result<(), Error> {
+    if !(MIN_FIB_N..=request.op.max_n()).contains(&request.n) {
+        return Err(Error::InputOutOfRange {
+            min: MIN_FIB_N,
+            max: request.op.max_n(),
+            got: request.n,
+        });
+    }
+    Ok(())
+}
+
+/// Proves the requested operation. Fibonacci goes through [`phi`], so it
+/// shares the cache and history with `/fib`.
+pub fn compute(request: &ComputeRequest) -> Result<ComputeResult, Error> {
+    validate_compute(request)?;
+
+    match request.op {
+        Op::Fib => {
+            let fib = phi(request.n, Mode::Nth, request.encoding)?;
+            Ok(ComputeResult {
+                op: Op::Fib,
+                n: request.n,
+                result: fib.result,
+                journal_hex: fib.journal_hex,
+                encoding: fib.encoding,
+                proof: fib.proof,
+                generated_at: fib.generated_at,
+                proving_time_ms: fib.proving_time_ms,
+                prover_version: fib.prover_version,
+            })
+        }
+        Op::Factorial => prove_factorial(request.n, request.encoding),
+    }
+}
+
+/// Proves n! in the zkVM and verifies the resulting receipt.
+fn prove_factorial(n: u64, encoding: Encoding) -> Result<ComputeResult, Error> {
+    debug!("Proving {}!", n);
+
+    let (receipt, generated_at, proving_time_ms) =
+        run_guest(n, Mode::default(), Op::Factorial, None)?;
+
+    // Decode the guest's output (the operation, mode, input and result).
+    let committed = Committed::decode(&receipt.journal)?;
+    committed.expect(Op::Factorial, Mode::default(), n)?;
+
+    // Serialize the receipt into bytes.
+    let receipt_bytes = bincode::serialize(&receipt).map_err(Error::Serialize)?;
+    record_proof(Op::Factorial, n, committed.mode, &receipt_bytes);
+
+    Ok(ComputeResult {
+        op: Op::Factorial,
+        n,
+        result: committed.result,
+        journal_hex: hex::encode(&receipt.journal.bytes),
+        encoding,
+        proof: encoding.encode(&receipt_bytes),
+        generated_at,
+        proving_time_ms,
+        prover_version: env!("CARGO_PKG_VERSION"),
+    })
+}
+
+/// Proves fib(a) and immediately verifies the receipt against the configured
+/// IMAGE_ID through the same path as [`verify`], as an end-to-end check.
+pub fn selftest(a: u64) -> Result<SelfTestResult, Error> {
+    let fib = phi(a, Mode::Nth, Encoding::Hex)?;
+    let request = VerifyRequest {
+        proof: fib.proof,
+        image_id: hex::encode(get_image_id()?),
+        encoding: Encoding::Hex,
+        mode: Some(Mode::Nth),
+        nonce: None,
+    };
+    let verification = verify(&request);
+    Ok(SelfTestResult {
+        result: fib.result,
+        proof: request.proof,
+        is_valid: verification.is_valid,
+    })
+}
+
+/// Verifies an encoded receipt against the given image id. Any decoding,
+/// deserialization or verification failure is reported as an invalid proof.
+pub fn verify(request: &VerifyRequest) -> VerifyResult {
+    let outcome = request
+        .encoding
+        .decode(&request.proof)
+        .and_then(|receipt_bytes| {
+            verify_receipt(
+                &receipt_bytes,
+                &request.image_id,
+                request.mode,
+                request.nonce.as_deref(),
+            )
+        });
+    verify_result(outcome, &request.image_id, request.mode)
+}
+
+/// Verifies raw bincode receipt bytes like [`verify`], skipping the text
+/// encoding of the proof.
+pub fn verify_raw(receipt_bytes: &[u8], image_id: &str, mode: Option<Mode>) -> VerifyResult {
+    let outcome = verify_receipt(receipt_bytes, image_id, mode, None);
+    verify_result(outcome, image_id, mode)
+}
+
+/// Turns the outcome of a verification into a result and records it in the
+/// history.
+fn verify_result(
+    outcome: Result<Committed, Error>,
+    image_id: &str,
+    mode: Option<Mode>,
+) -> VerifyResult {
+    let result = match outcome {
+        Ok(committed) => VerifyResult {
+            is_valid: true,
+            op: Some(committed.op),
+            mode: Some(committed.mode),
+            input: Some(committed.input),
+            result: Some(committed.result),
+            sequence: committed.sequence,
+            nonce: committed.nonce.map(hex::encode),
+        },
+        Err(_) => VerifyResult {
+            is_valid: false,
+            op: None,
+            mode,
+            input: None,
+            result: None,
+            sequence: None,
+            nonce: None,
+        },
+    };
+    if let Some(history) = history() {
+        let input = result.input.map(|input| input.to_string());
+        let op = result.op.map(|op| op.as_str());
+        let mode = result.mode.map_or("unknown", |mode| mode.as_str());
+        if let Err(e) =
+            history.record_verification(image_id, op, mode, input.as_deref(), result.is_valid)
+        {
+            warn!("Failed to record verification in history: {}", e);
+        }
+    }
+    result
+}
+
+/// Verifies a receipt and decodes its journal. When `mode` is given, the
+/// receipt must be a Fibonacci receipt proven in that mode, and when `nonce`
+/// is given, it must commit that challenge nonce. A committed nonce must be an
+/// outstanding challenge, which is redeemed once the receipt is accepted.
+fn verify_receipt(
+    receipt_bytes: &[u8],
+    image_id: &str,
+    mode: Option<Mode>,
+    nonce: Option<&str>,
+) -> Result<Committed, Error> {
+    // Decode the image id and the receipt.
+    let image_id = parse_image_id(image_id)?;
+    let receipt: Receipt = bincode::deserialize(receipt_bytes).map_err(Error::Deserialize)?;
+
+    // Verify the receipt and decode the committed result.
+    receipt
+        .verify(image_id)
+        .map_err(|e| Error::Verification(e.to_string()))?;
+    let committed = Committed::decode(&receipt.journal)?;
+    if let Some(mode) = mode {
+        committed.expect(Op::Fib, mode, committed.input)?;
+    }
+    if let Some(nonce) = nonce {
+        if committed.nonce != Some(challenge::parse_nonce(nonce)?) {
+            return Err(Error::Challenge(
+                "the receipt does not commit the expected nonce".to_string(),
+            ));
+        }
+    }
+    if let Some(nonce) = &committed.nonce {
+        challenges().redeem(nonce)?;
+    }
+    Ok(committed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_short_image_ids() {
+        let outcome = parse_image_id(&"00".repeat(16));
+        assert!(matches!(outcome, Err(Error::ImageIdLength(16))));
+        assert!(parse_image_id(&"00".repeat(32)).is_ok());
+        assert!(matches!(parse_image_id("zz"), Err(Error::ImageIdHex(_))));
+    }
+
+    #[test]
+    fn validates_the_u64_boundary() {
+        assert!(validate_input(93, Mode::Nth).is_ok());
+        let outcome = validate_input(94, Mode::Nth);
+        assert!(matches!(
+            outcome,
+            Err(Error::InputOutOfRange {
+                min: 1,
+                max: 93,
+                got: 94
+            })
+        ));
+        assert!(validate_input(94, Mode::Wide).is_ok());
+        assert!(validate_input(0, Mode::Nth).is_err());
+    }
+
+    #[test]
+    fn reports_readiness_from_the_image_id() {
+        // The only test that sets IMAGE_ID, so no other test observes it.
+        env::remove_var("IMAGE_ID");
+        assert!(check_ready(false).is_ok());
+
+        env::set_var("IMAGE_ID", "00".repeat(16));
+        let outcome = check_ready(false);
+        env::remove_var("IMAGE_ID");
+        assert!(matches!(outcome, Err(Error::ImageIdLength(16))));
+    }
+
+    // Builds a journal the way the guest commits values.
+    fn journal<T: Serialize>(value: &T) -> Journal {
+        let words = risc0_zkvm::serde::to_vec(value).unwrap();
+        Journal::new(words.iter().flat_map(|word| word.to_le_bytes()).collect())
+    }
+
+    #[test]
+    fn decodes_nth_journal() {
+        let committed =
+            Committed::decode(&journal(&(0u32, 0u32, 10u64, None::<Nonce>, 55u64))).unwrap();
+        assert!(committed.op == Op::Fib && committed.mode == Mode::Nth);
+        assert_eq!((committed.input, committed.result), (10, 55));
+        assert!(committed.expect(Op::Fib, Mode::Nth, 10).is_ok());
+    }
+
+    #[test]
+    fn decodes_wide_journal_by_committed_mode() {
+        let fib_100: u128 = 354_224_848_179_261_915_075;
+        let committed =
+            Committed::decode(&journal(&(0u32, 1u32, 100u64, None::<Nonce>, fib_100))).unwrap();
+        assert!(committed.mode == Mode::Wide);
+        assert_eq!(committed.result, fib_100);
+        assert!(committed.expect(Op::Fib, Mode::Nth, 100).is_err());
+    }
+
+    #[test]
+    fn decodes_sequence_journal() {
+        let sequence = vec![1u64, 1, 2, 3, 5];
+        let committed =
+            Committed::decode(&journal(&(0u32, 2u32, 5u64, None::<Nonce>, &sequence))).unwrap();
+        assert_eq!(committed.sequence, Some(sequence));
+        assert_eq!(committed.result, 5);
+    }
+
+    #[test]
+    fn decodes_factorial_journal() {
+        let committed =
+            Committed::decode(&journal(&(1u32, 0u32, 5u64, None::<Nonce>, 120u128))).unwrap();
+        assert!(committed.op == Op::Factorial);
+        assert_eq!(committed.result, 120);
+        assert!(committed.expect(Op::Fib, Mode::Nth, 5).is_err());
+    }
+
+    #[test]
+    fn decodes_committed_nonce() {
+        let nonce: Nonce = [7; 16];
+        let committed =
+            Committed::decode(&journal(&(0u32, 0u32, 10u64, Some(nonce), 55u64))).unwrap();
+        assert_eq!(committed.nonce, Some(nonce));
+        assert_eq!(committed.result, 55);
+    }
+
+    // Builds a result around the given journal, as if it had been proven.
+    fn raw_result(journal: &Journal) -> RawFibResult {
+        RawFibResult {
+            mode: Mode::Nth,
+            result: 55,
+            sequence: None,
+            nonce: None,
+            journal: journal.bytes.clone(),
+            proof: vec![4, 5, 6],
+            generated_at: 1_700_000_000_000,
+            proving_time_ms: 1234,
+            circuit: CIRCUIT,
+            prover_version: env!("CARGO_PKG_VERSION"),
+        }
+    }
+
+    #[test]
+    fn encodes_the_journal_as_hex() {
+        let fib =
+            raw_result(&journal(&(0u32, 0u32, 10u64, None::<Nonce>, 55u64))).encode(Encoding::Hex);
+        let bytes = hex::decode(&fib.journal_hex).unwrap();
+        let committed = Committed::decode(&Journal::new(bytes)).unwrap();
+        assert!(committed.op == Op::Fib && committed.mode == Mode::Nth);
+        assert_eq!((committed.input, committed.result), (10, 55));
+    }
+
+    #[test]
+    fn reports_proof_metadata() {
+        let fib =
+            raw_result(&journal(&(0u32, 0u32, 10u64, None::<Nonce>, 55u64))).encode(Encoding::Hex);
+        let body = serde_json::to_value(&fib).unwrap();
+        assert_eq!(body["generated_at"], 1_700_000_000_000u64);
+        assert_eq!(body["proving_time_ms"], 1234);
+        assert_eq!(body["circuit"], "fib");
+        assert_eq!(body["prover_version"], env!("CARGO_PKG_VERSION"));
+    }
+
+    #[test]
+    fn round_trips_base64_proofs() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        let encoded = Encoding::Base64.encode(&bytes);
+        assert_eq!(Encoding::Base64.decode(&encoded).unwrap(), bytes);
+        assert!(matches!(
+            Encoding::Base64.decode("not base64!"),
+            Err(Error::ProofBase64(_))
+        ));
+    }
+
+    #[test]
+    fn reports_undecodable_base64_proofs_as_invalid() {
+        for proof in ["not base64!", &Encoding::Base64.encode(b"not a receipt")] {
+            let request = VerifyRequest {
+                proof: proof.to_string(),
+                image_id: DEFAULT_IMAGE_ID.to_string(),
+                encoding: Encoding::Base64,
+                mode: None,
+                nonce: None,
+            };
+            assert!(!verify(&request).is_valid, "{}", proof);
+        }
+    }
+
+    // Proves for real, so it needs a prover backend and takes a while.
+    #[test]
+    #[ignore]
+    fn verifies_a_proven_receipt_in_base64() {
+        let fib = phi(10, Mode::Nth, Encoding::Base64).unwrap();
+        let request = VerifyRequest {
+            proof: fib.proof,
+            image_id: DEFAULT_IMAGE_ID.to_string(),
+            encoding: Encoding::Base64,
+            mode: Some(Mode::Nth),
+            nonce: None,
+        };
+        let verification = verify(&request);
+        assert!(verification.is_valid);
+        assert_eq!(verification.result, Some(55));
+    }
+
+    #[test]
+    fn rejects_trailing_words() {
+        let outcome = Committed::decode(&journal(&(0u32, 0u32, 10u64, None::<Nonce>, 55u64, 0u32)));
+        assert!(matches!(outcome, Err(Error::Decode(_))));
+    }
+
+    #[test]
+    fn rejects_unknown_tags() {
+        assert!(Committed::decode(&journal(&(2u32, 0u32, 10u64, None::<Nonce>, 55u64))).is_err());
+        assert!(Committed::decode(&journal(&(0u32, 3u32, 10u64, None::<Nonce>, 55u64))).is_err());
+        assert!(Committed::decode(&journal(&(1u32, 1u32, 5u64, None::<Nonce>, 120u128))).is_err());
+    }
+
+    #[test]
+    fn reports_missing_r0vm_as_unavailable() {
+        let missing = io::Error::from(io::ErrorKind::NotFound);
+        assert!(matches!(prove_error(&missing), Error::ProverUnavailable(_)));
+        let failed = io::Error::from(io::ErrorKind::BrokenPipe);
+        assert!(matches!(prove_error(&failed), Error::Prove(_)));
+    }
+
+    #[test]
+    fn reports_unsupported_provers_as_unavailable() {
+        // The only test that sets RISC0_PROVER, so no other test observes it.
+        for prover in ["bogus", "local"] {
+            env::set_var("RISC0_PROVER", prover);
+            let outcome = select_prover();
+            env::remove_var("RISC0_PROVER");
+            assert!(
+                matches!(outcome, Err(Error::ProverUnavailable(_))),
+                "{}",
+                prover
+            );
+        }
+    }
+
+    #[test]
+    fn embeds_the_guest_matching_the_default_image_id() {
+        let computed = compute_image_id(GUEST_ELF).unwrap();
+        assert_eq!(
+            computed.as_bytes(),
+            parse_image_id(DEFAULT_IMAGE_ID).unwrap().as_slice()
+        );
+    }
+
+    #[test]
+    fn validates_compute_inputs_per_operation() {
+        let request = |op, n| ComputeRequest {
+            op,
+            n,
+            encoding: Encoding::default(),
+        };
+        assert!(validate_compute(&request(Op::Fib, 93)).is_ok());
+        assert!(validate_compute(&request(Op::Factorial, MAX_FACTORIAL_N)).is_ok());
+        assert!(matches!(
+            validate_compute(&request(Op::Factorial, MAX_FACTORIAL_N + 1)),
+            Err(Error::InputOutOfRange {
+                max: MAX_FACTORIAL_N,
+                ..
+            })
+        ));
+        assert!(matches!(
+            validate_compute(&request(Op::Fib, 94)),
+            Err(Error::InputOutOfRange { max: 93, .. })
+        ));
+        assert!(validate_compute(&request(Op::Factorial, 0)).is_err());
+    }
+
+    #[test]
+    fn serves_repeated_inputs_from_the_cache() {
+        // A result no prover could produce, so a hit is the only way to get it.
+        let cached = RawFibResult {
+            mode: Mode::Wide,
+            result: 5_527_939_700_884_757,
+            sequence: None,
+            nonce: None,
+            journal: vec![1, 2, 3],
+            proof: vec![4, 5, 6],
+            generated_at: 0,
+            proving_time_ms: 0,
+            circuit: CIRCUIT,
+            prover_version: env!("CARGO_PKG_VERSION"),
+        };
+        let cache = fib_cache().expect("The cache is enabled by default");
+        cache.lock().unwrap().put((77, Mode::Wide), cached);
 
-    // Serialize the receipt into bytes and encode it as a hex string.
-    let receipt_bytes = bincode::serialize(&receipt)
-        .map_err(|e| format!("Failed to serialize receipt: {}", e))?;
-    let proof = hex::encode(receipt_bytes);
+        for _ in 0..2 {
+            let proven = phi_raw(77, Mode::Wide).unwrap();
+            assert_eq!(proven.proof, vec![4, 5, 6]);
+            assert_eq!(proven.result, 5_527_939_700_884_757);
+        }
+    }
 
-    Ok(FibResult { result, proof })
+    #[test]
+    fn rejects_empty_journal() {
+        let outcome = Committed::decode(&Journal::new(Vec::new()));
+        assert!(matches!(outcome, Err(Error::EmptyJournal)));
+    }
 }
@@ -0,0 +1,186 @@
+use crate::error::{Error, ErrorResponse};
+use crate::jobs::JobRequest;
+use crate::slots;
+use crate::worker::{self, FibResult};
+use futures_util::{Sink, SinkExt, StreamExt};
+use serde::Serialize;
+use std::time::Duration;
+use warp::ws::{Message, WebSocket};
+
+// Define the progress messages streamed to the client while proving.
+#[derive(Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+enum Progress {
+    Proving,
+    Done { result: FibResult },
+    Failed(ErrorResponse),
+}
+
+/// Sends a progress message as a JSON text frame.
+async fn send<S>(tx: &mut S, progress: &Progress) -> Result<(), warp::Error>
+where
+    S: Sink<Message, Error = warp::Error> + Unpin,
+{
+    let text = serde_json::to_string(progress).unwrap();
+    tx.send(Message::text(text)).await
+}
+
+/// Reports a failure to the client and closes the socket.
+async fn fail<S>(tx: &mut S, error: ErrorResponse)
+where
+    S: Sink<Message, Error = warp::Error> + Unpin,
+{
+    let _ = send(tx, &Progress::Failed(error)).await;
+    let _ = tx.send(Message::close()).await;
+}
+
+/// Reads a single `{"n": ..}` request from the socket, reports that proving
+/// started, proves on a blocking thread and reports the outcome before
+/// closing. A proof that takes longer than `prove_timeout` is reported as
+/// failed. If the client disconnects mid-proof the task is abandoned.
+pub async fn handle_socket(socket: WebSocket, prove_timeout: Duration) {
+    serve_socket(socket, prove_timeout, |request| {
+        worker::phi(request.n, request.mode, request.encoding)
+    })
+    .await
+}
+
+/// Serves a socket like [`handle_socket`], proving the request with `prover`.
+async fn serve_socket<F>(socket: WebSocket, prove_timeout: Duration, prover: F)
+where
+    F: FnOnce(JobRequest) -> Result<FibResult, Error> + Send + 'static,
+{
+    let (mut tx, mut rx) = socket.split();
+
+    // Wait for the request message.
+    let request: JobRequest = match rx.next().await {
+        Some(Ok(message)) if message.is_text() => {
+            match serde_json::from_str(message.to_str().unwrap_or_default()) {
+                Ok(request) => request,
+                Err(e) => {
+                    let error =
+                        ErrorResponse::new("invalid_body", format!("Invalid request: {}", e));
+                    return fail(&mut tx, error).await;
+                }
+            }
+        }
+        _ => return,
+    };
+
+    // Reject out-of-range inputs before invoking the prover.
+    if let Err(e) = worker::validate_input(request.n, request.mode) {
+        return fail(&mut tx, ErrorResponse::from(&e)).await;
+    }
+
+    if send(&mut tx, &Progress::Proving).await.is_err() {
+        return;
+    }
+
+    // Prove on a blocking thread, once a proving slot is free, while watching
+    // for the client going away.
+    let task = slots::run(prove_timeout, move || prover(request));
+    tokio::pin!(task);
+    let outcome = loop {
+        tokio::select! {
+            outcome = &mut task => break outcome,
+            message = rx.next() => match message {
+                Some(Ok(message)) if !message.is_close() => continue,
+                _ => return,
+            },
+        }
+    };
+
+    let progress = match outcome {
+        Ok(result) => Progress::Done { result },
+        Err(e) => Progress::Failed(ErrorResponse::from(&e)),
+    };
+    let _ = send(&mut tx, &progress).await;
+    let _ = tx.send(Message::close()).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use warp::Filter;
+
+    // Route serving sockets through `prover`.
+    fn route<F>(
+        prover: F,
+    ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone
+    where
+        F: FnOnce(JobRequest) -> Result<FibResult, Error> + Clone + Send + 'static,
+    {
+        warp::ws().map(move |ws: warp::ws::Ws| {
+            let prover = prover.clone();
+            ws.on_upgrade(move |socket| serve_socket(socket, Duration::from_secs(60), prover))
+        })
+    }
+
+    // Reads the next frame as JSON.
+    async fn recv_json(client: &mut warp::test::WsClient) -> serde_json::Value {
+        let message = client.recv().await.unwrap();
+        serde_json::from_str(message.to_str().unwrap()).unwrap()
+    }
+
+    // Checks that the server closed the socket after its last frame.
+    async fn assert_closed(client: &mut warp::test::WsClient) {
+        match client.recv().await {
+            Ok(message) => assert!(message.is_close(), "unexpected frame {:?}", message),
+            Err(_) => return,
+        }
+        assert!(client.recv_closed().await.is_ok());
+    }
+
+    fn unreachable_prover(_: JobRequest) -> Result<FibResult, Error> {
+        panic!("the request should not be proven")
+    }
+
+    #[tokio::test]
+    async fn fails_and_closes_on_bad_requests() {
+        for (request, kind) in [
+            (r#"{"n":0}"#, "input_out_of_range"),
+            (r#"{"n":"#, "invalid_body"),
+        ] {
+            let mut client = warp::test::ws()
+                .handshake(route(unreachable_prover))
+                .await
+                .unwrap();
+            client.send_text(request).await;
+
+            let frame = recv_json(&mut client).await;
+            assert_eq!(frame["status"], "failed", "{}", request);
+            assert_eq!(frame["kind"], kind, "{}", request);
+            assert_closed(&mut client).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_proving_before_the_result() {
+        let prover = |request: JobRequest| {
+            Ok(FibResult {
+                mode: request.mode,
+                result: 55,
+                sequence: None,
+                nonce: None,
+                journal_hex: String::new(),
+                encoding: request.encoding,
+                proof: "00".to_string(),
+                generated_at: 0,
+                proving_time_ms: 0,
+                circuit: "fib",
+                prover_version: env!("CARGO_PKG_VERSION"),
+            })
+        };
+        let mut client = warp::test::ws().handshake(route(prover)).await.unwrap();
+        client.send_text(r#"{"n":10}"#).await;
+
+        assert_eq!(
+            recv_json(&mut client).await,
+            serde_json::json!({ "status": "proving" })
+        );
+        let done = recv_json(&mut client).await;
+        assert_eq!(done["status"], "done");
+        assert_eq!(done["result"]["result"], 55);
+        assert_closed(&mut client).await;
+    }
+}
@@ -0,0 +1,43 @@
+use std::process::{Command, Output};
+
+// Image id of the embedded guest.
+const IMAGE_ID: &str = "4c2b049124a792ebb4e9cff145c2dbba3219c329fc1407c8dca2b202218900ba";
+
+// Runs the binary with the given arguments, without a proof history.
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_risc-v"))
+        .args(args)
+        .env_remove("DB_PATH")
+        .output()
+        .expect("Failed to run the binary")
+}
+
+#[test]
+fn reports_bad_proofs_as_invalid() {
+    let output = run(&["verify", "--proof", "00", "--image-id", IMAGE_ID]);
+    assert!(!output.status.success());
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(result["is_valid"], false);
+}
+
+#[test]
+fn rejects_out_of_range_inputs() {
+    let output = run(&["prove", "--n", "0"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Input out of range: expected 1 <= n <= 93, got 0"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn prints_usage() {
+    let output = run(&["--help"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    for command in ["serve", "prove", "verify"] {
+        assert!(stdout.contains(command), "{}", stdout);
+    }
+}